@@ -0,0 +1,284 @@
+use crate::SpeedTestResult;
+use apache_avro::{rabin::Rabin, types::Value, Reader, Schema, Writer};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// Schema of the records `--format avro` writes. Each container file carries
+/// it, along with its Rabin fingerprint under `FINGERPRINT_KEY`.
+pub const SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "SpeedTestResult",
+  "namespace": "speedtest_cli",
+  "fields": [
+    {"name": "run_id", "type": "string"},
+    {"name": "timestamp", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+    {"name": "server_id", "type": "string"},
+    {"name": "download_speed_mbps", "type": "float"},
+    {"name": "upload_speed_mbps", "type": "float"},
+    {"name": "ping_ms", "type": "float"},
+    {"name": "jitter_ms", "type": "float"},
+    {"name": "ttfb_ms", "type": "float"},
+    {"name": "packet_loss_percent", "type": ["null", "float"], "default": null},
+    {"name": "loaded_ping_ms", "type": ["null", "float"], "default": null},
+    {"name": "upload_loaded_ping_ms", "type": ["null", "float"], "default": null},
+    {"name": "download_streams", "type": "int"},
+    {"name": "download_truncated", "type": "boolean"},
+    {"name": "unreliable", "type": "boolean"},
+    {"name": "retries", "type": "int"},
+    {"name": "http_version", "type": "string"},
+    {"name": "asn", "type": ["null", "long"], "default": null},
+    {"name": "isp", "type": ["null", "string"], "default": null},
+    {"name": "download_histogram", "type": ["null", {"type": "array", "items": {
+      "type": "record",
+      "name": "HistogramBucket",
+      "fields": [
+        {"name": "bucket_ms", "type": "long"},
+        {"name": "mbps", "type": "double"}
+      ]
+    }}], "default": null},
+    {"name": "efficiency_percent", "type": ["null", "float"], "default": null},
+    {"name": "download_checksum_valid", "type": ["null", "boolean"], "default": null},
+    {"name": "signature", "type": ["null", "string"], "default": null},
+    {"name": "cold_connections", "type": "boolean", "default": false},
+    {"name": "cached", "type": "boolean", "default": false},
+    {"name": "download_burst_mbps", "type": ["null", "float"], "default": null},
+    {"name": "download_sustained_mbps", "type": ["null", "float"], "default": null},
+    {"name": "download_cache_suspected", "type": ["null", "boolean"], "default": null},
+    {"name": "download_steady_mbps", "type": ["null", "float"], "default": null},
+    {"name": "egress_interface", "type": ["null", "string"], "default": null},
+    {"name": "final_url", "type": ["null", "string"], "default": null},
+    {"name": "jitter_hosts", "type": {"type": "array", "items": "string"}, "default": []},
+    {"name": "download_size_mb", "type": ["null", "int"], "default": null},
+    {"name": "download_within_target", "type": ["null", "boolean"], "default": null},
+    {"name": "latency_outliers", "type": ["null", "int"], "default": null},
+    {"name": "jitter_outliers", "type": ["null", "int"], "default": null},
+    {"name": "capacity_mbps", "type": ["null", "float"], "default": null},
+    {"name": "capacity_probes_mbps", "type": {"type": "array", "items": "float"}, "default": []},
+    {"name": "upload_mode", "type": ["null", "string"], "default": null},
+    {"name": "connection_profile", "type": ["null", {
+      "type": "record",
+      "name": "ConnectionProfile",
+      "fields": [
+        {"name": "dns_ms", "type": "float"},
+        {"name": "tcp_connect_ms", "type": "float"},
+        {"name": "tls_ms", "type": ["null", "float"], "default": null},
+        {"name": "ttfb_ms", "type": "float"},
+        {"name": "total_ms", "type": "float"}
+      ]
+    }], "default": null}
+  ]
+}"#;
+
+/// File metadata key holding the hex Rabin fingerprint of `SCHEMA`.
+const FINGERPRINT_KEY: &str = "speedtest.schema.fingerprint";
+
+fn schema() -> Schema {
+    Schema::parse_str(SCHEMA).expect("the Avro schema is valid")
+}
+
+/// Hex Rabin (CRC-64-AVRO) fingerprint of `SCHEMA`.
+pub fn fingerprint() -> String {
+    schema().fingerprint::<Rabin>().to_string()
+}
+
+/// A complete Avro object container file holding `results`.
+pub fn encode(results: &[SpeedTestResult]) -> Vec<u8> {
+    let schema = schema();
+    let mut writer = Writer::new(&schema, Vec::new()).unwrap();
+    writer.add_user_metadata(FINGERPRINT_KEY.to_string(), fingerprint()).unwrap();
+    for result in results {
+        writer.append_value(record(result)).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+/// Blocks holding `results` that continue the container file `existing`,
+/// which must have been written with the same schema. Only the header is
+/// read; its sync marker is the one the new blocks end with.
+pub fn append(existing: impl Read, results: &[SpeedTestResult]) -> Result<Vec<u8>, String> {
+    let schema = schema();
+    let mut header = Recording { inner: existing, read: Vec::new() };
+    let reader = Reader::new(&mut header).map_err(|e| format!("not an Avro container file: {}", e))?;
+    if reader.writer_schema().canonical_form() != schema.canonical_form() {
+        return Err("the file was written with a different schema; write to a new file".to_string());
+    }
+    drop(reader);
+    // The header ends with the marker, and `Reader` reads nothing past it before the first record.
+    let marker = header.read[header.read.len() - 16..].try_into().unwrap();
+    let mut writer = Writer::append_to(&schema, Vec::new(), marker).unwrap();
+    for result in results {
+        writer.append_value(record(result)).unwrap();
+    }
+    Ok(writer.into_inner().unwrap())
+}
+
+/// Appends `results` to the container file at `path`, creating it first if
+/// it doesn't exist yet, so every `--interval` cycle lands in one file.
+pub fn append_to_file(path: &Path, results: &[SpeedTestResult]) -> Result<(), String> {
+    let mut file = match fs::OpenOptions::new().read(true).append(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return write_new(path, results),
+        Err(e) => return Err(e.to_string()),
+    };
+    if file.metadata().map_err(|e| e.to_string())?.len() == 0 {
+        return write_new(path, results);
+    }
+    let blocks = append(&mut file, results)?;
+    file.write_all(&blocks).map_err(|e| e.to_string())
+}
+
+fn write_new(path: &Path, results: &[SpeedTestResult]) -> Result<(), String> {
+    fs::write(path, encode(results)).map_err(|e| e.to_string())
+}
+
+/// Keeps a copy of the bytes read through it.
+struct Recording<R> {
+    inner: R,
+    read: Vec<u8>,
+}
+
+impl<R: Read> Read for Recording<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.read.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+fn record(result: &SpeedTestResult) -> Value {
+    let float = |value: Option<f32>| Value::from(value.map(Value::Float));
+    let int = |value: Option<u32>| Value::from(value.map(|value| Value::Int(value as i32)));
+    let boolean = |value: Option<bool>| Value::from(value.map(Value::Boolean));
+    let string = |value: &Option<String>| Value::from(value.clone().map(Value::String));
+    let histogram = result.download_histogram.as_ref().map(|buckets| {
+        Value::Array(
+            buckets
+                .iter()
+                .map(|bucket| {
+                    Value::Record(vec![
+                        ("bucket_ms".to_string(), Value::Long(bucket.bucket_ms as i64)),
+                        ("mbps".to_string(), Value::Double(bucket.mbps)),
+                    ])
+                })
+                .collect(),
+        )
+    });
+    let profile = result.connection_profile.as_ref().map(|profile| {
+        Value::Record(vec![
+            ("dns_ms".to_string(), Value::Float(profile.dns_ms)),
+            ("tcp_connect_ms".to_string(), Value::Float(profile.tcp_connect_ms)),
+            ("tls_ms".to_string(), float(profile.tls_ms)),
+            ("ttfb_ms".to_string(), Value::Float(profile.ttfb_ms)),
+            ("total_ms".to_string(), Value::Float(profile.total_ms)),
+        ])
+    });
+    Value::Record(vec![
+        ("run_id".to_string(), Value::String(result.run_id.to_string())),
+        ("timestamp".to_string(), Value::TimestampMillis(result.timestamp.timestamp_millis())),
+        ("server_id".to_string(), Value::String(result.server_id.clone())),
+        ("download_speed_mbps".to_string(), Value::Float(result.download_speed_mbps)),
+        ("upload_speed_mbps".to_string(), Value::Float(result.upload_speed_mbps)),
+        ("ping_ms".to_string(), Value::Float(result.ping_ms)),
+        ("jitter_ms".to_string(), Value::Float(result.jitter_ms)),
+        ("ttfb_ms".to_string(), Value::Float(result.ttfb_ms)),
+        ("packet_loss_percent".to_string(), float(result.packet_loss_percent)),
+        ("loaded_ping_ms".to_string(), float(result.loaded_ping_ms)),
+        ("upload_loaded_ping_ms".to_string(), float(result.upload_loaded_ping_ms)),
+        ("download_streams".to_string(), Value::Int(result.download_streams as i32)),
+        ("download_truncated".to_string(), Value::Boolean(result.download_truncated)),
+        ("unreliable".to_string(), Value::Boolean(result.unreliable)),
+        ("retries".to_string(), Value::Int(result.retries as i32)),
+        ("http_version".to_string(), Value::String(result.http_version.clone())),
+        ("asn".to_string(), Value::from(result.asn.map(|asn| Value::Long(asn as i64)))),
+        ("isp".to_string(), string(&result.isp)),
+        ("download_histogram".to_string(), Value::from(histogram)),
+        ("efficiency_percent".to_string(), float(result.efficiency_percent)),
+        ("download_checksum_valid".to_string(), boolean(result.download_checksum_valid)),
+        ("signature".to_string(), string(&result.signature)),
+        ("cold_connections".to_string(), Value::Boolean(result.cold_connections)),
+        ("cached".to_string(), Value::Boolean(result.cached)),
+        ("download_burst_mbps".to_string(), float(result.download_burst_mbps)),
+        ("download_sustained_mbps".to_string(), float(result.download_sustained_mbps)),
+        ("download_cache_suspected".to_string(), boolean(result.download_cache_suspected)),
+        ("download_steady_mbps".to_string(), float(result.download_steady_mbps)),
+        ("egress_interface".to_string(), string(&result.egress_interface)),
+        ("final_url".to_string(), string(&result.final_url)),
+        ("jitter_hosts".to_string(), Value::Array(result.jitter_hosts.iter().cloned().map(Value::String).collect())),
+        ("download_size_mb".to_string(), int(result.download_size_mb)),
+        ("download_within_target".to_string(), boolean(result.download_within_target)),
+        ("latency_outliers".to_string(), int(result.latency_outliers)),
+        ("jitter_outliers".to_string(), int(result.jitter_outliers)),
+        ("capacity_mbps".to_string(), float(result.capacity_mbps)),
+        (
+            "capacity_probes_mbps".to_string(),
+            Value::Array(result.capacity_probes_mbps.iter().map(|&mbps| Value::Float(mbps)).collect()),
+        ),
+        ("upload_mode".to_string(), string(&result.upload_mode)),
+        ("connection_profile".to_string(), Value::from(profile)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appended_records_read_back_from_one_container() {
+        let result = |server: &str| SpeedTestResult {
+            server_id: server.to_string(),
+            download_speed_mbps: 250.0,
+            asn: Some(13335),
+            ..Default::default()
+        };
+        let mut file = encode(&[result("us")]);
+        file.extend(append(&file[..], &[result("eu"), result("ap")]).unwrap());
+        // A torn last block doesn't change the marker later blocks are written with.
+        let torn = [&file[..], b"torn"].concat();
+        assert_eq!(append(&torn[..], &[result("eu")]).unwrap(), append(&file[..], &[result("eu")]).unwrap());
+
+        let reader = Reader::new(&file[..]).unwrap();
+        assert_eq!(reader.user_metadata()[FINGERPRINT_KEY], fingerprint().into_bytes());
+        let servers: Vec<Value> = reader
+            .map(|record| match record.unwrap() {
+                Value::Record(fields) => fields.into_iter().find(|(name, _)| name == "server_id").unwrap().1,
+                other => panic!("not a record: {:?}", other),
+            })
+            .collect();
+        assert_eq!(servers, ["us", "eu", "ap"].map(|id| Value::String(id.to_string())));
+
+        assert!(append(&b"not avro at all"[..], &[result("us")]).is_err());
+
+        let path = std::env::temp_dir().join(format!("speedtest-avro-{}.avro", std::process::id()));
+        let _ = fs::remove_file(&path);
+        append_to_file(&path, &[result("us")]).unwrap();
+        append_to_file(&path, &[result("eu"), result("ap")]).unwrap();
+        assert_eq!(Reader::new(fs::File::open(&path).unwrap()).unwrap().count(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn records_carry_the_fields_protobuf_does() {
+        let result = SpeedTestResult {
+            signature: Some("abc123".to_string()),
+            cached: true,
+            jitter_hosts: vec!["one.example".to_string()],
+            download_histogram: Some(vec![crate::HistogramBucket { bucket_ms: 250, mbps: 80.0 }]),
+            connection_profile: Some(crate::ConnectionProfile { dns_ms: 1.0, tls_ms: None, ..Default::default() }),
+            ..Default::default()
+        };
+
+        let record = Reader::new(&encode(&[result])[..]).unwrap().next().unwrap().unwrap();
+        let Value::Record(fields) = record else { panic!("not a record: {:?}", record) };
+        let field = |name: &str| fields.iter().find(|(field, _)| field == name).unwrap().1.clone();
+        assert_eq!(field("signature"), Value::Union(1, Box::new(Value::String("abc123".to_string()))));
+        assert_eq!(field("cached"), Value::Boolean(true));
+        assert_eq!(field("jitter_hosts"), Value::Array(vec![Value::String("one.example".to_string())]));
+        assert_eq!(field("download_steady_mbps"), Value::Union(0, Box::new(Value::Null)));
+        let Value::Union(1, histogram) = field("download_histogram") else { panic!("no histogram") };
+        assert!(matches!(*histogram, Value::Array(ref buckets) if buckets.len() == 1));
+        assert!(matches!(field("connection_profile"), Value::Union(1, _)));
+    }
+}
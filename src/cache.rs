@@ -0,0 +1,76 @@
+use crate::{output, SpeedTestResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+/// What a cached run tested: its servers and the flags that shape the test
+/// (as `flag=value`), so results only stand in for a run that would measure the same way.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct CacheKey {
+    pub servers: Vec<String>,
+    pub options: Vec<String>,
+}
+
+/// The `--cache-ttl` file: the last run's results and what it tested.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    key: CacheKey,
+    results: Vec<SpeedTestResult>,
+}
+
+/// The cached results for `key` if every one of them is younger than
+/// `ttl`, marked as `cached`. A missing or unreadable cache is a miss.
+pub fn load(path: &Path, key: &CacheKey, ttl: Duration, now: DateTime<Utc>) -> Option<Vec<SpeedTestResult>> {
+    let entry: CacheEntry = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    let oldest = now - chrono::Duration::from_std(ttl).ok()?;
+    if entry.key != *key || entry.results.is_empty() || entry.results.iter().any(|r| r.timestamp < oldest) {
+        return None;
+    }
+    Some(entry.results.into_iter().map(|result| SpeedTestResult { cached: true, ..result }).collect())
+}
+
+/// Replaces the cache with `results`. Runs that measured nothing are not
+/// cached, so a caller retrying after an outage gets a fresh test.
+pub fn store(path: &Path, key: &CacheKey, results: &[SpeedTestResult]) -> std::io::Result<()> {
+    if results.iter().all(SpeedTestResult::is_failed) {
+        return Ok(());
+    }
+    let entry = CacheEntry { key: key.clone(), results: results.to_vec() };
+    output::write_atomic(path, &serde_json::to_vec(&entry).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_are_reused_until_they_expire() {
+        let dir = std::env::temp_dir().join(format!("speedtest-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json");
+        let key = CacheKey { servers: vec!["cloudflare".to_string()], options: vec!["parallel=1".to_string()] };
+        let now = Utc::now();
+        let result = SpeedTestResult { timestamp: now, download_speed_mbps: 100.0, ..Default::default() };
+        store(&path, &key, &[result]).unwrap();
+
+        let cached = load(&path, &key, Duration::from_secs(60), now).unwrap();
+        assert!(cached[0].cached);
+        assert_eq!(cached[0].download_speed_mbps, 100.0);
+
+        let later = now + chrono::Duration::seconds(61);
+        assert!(load(&path, &key, Duration::from_secs(60), later).is_none());
+        let lab = CacheKey { servers: vec!["lab".to_string()], ..key.clone() };
+        assert!(load(&path, &lab, Duration::from_secs(60), now).is_none());
+        let parallel = CacheKey { options: vec!["parallel=4".to_string()], ..key };
+        assert!(load(&path, &parallel, Duration::from_secs(60), now).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_runs_are_not_cached() {
+        let path = std::env::temp_dir().join(format!("speedtest-cache-failed-{}.json", std::process::id()));
+        store(&path, &CacheKey::default(), &[SpeedTestResult::default()]).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{servers::Server, summary::Stats};
+use reqwest::Client;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Latency requests sent to judge the link's delay and its variation.
+const LATENCY_SAMPLES: usize = 5;
+/// Size of the single throughput probe; big enough to get past TCP slow
+/// start on most links while still finishing in seconds.
+const DOWNLOAD_BYTES: u64 = 10_000_000;
+/// Time allowed for each probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Geostationary satellite links can't go below roughly half a second.
+const SATELLITE_MIN_LATENCY_MS: f64 = 500.0;
+/// Latency that swings this much between probes points at a radio link.
+const MOBILE_LATENCY_SPREAD_MS: f64 = 20.0;
+const FIBER_MAX_LATENCY_MS: f64 = 10.0;
+const FIBER_MIN_DOWNLOAD_MBPS: f64 = 200.0;
+const CABLE_MIN_DOWNLOAD_MBPS: f64 = 50.0;
+
+/// A one-word verdict on what kind of link a server is reached over, with
+/// the measurements and rule that led to it.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Classification {
+    pub server_id: String,
+    pub label: &'static str,
+    pub min_latency_ms: f64,
+    pub latency_spread_ms: f64,
+    pub download_speed_mbps: f64,
+    pub reasons: Vec<String>,
+}
+
+impl Classification {
+    /// Applies the heuristics in order, first match wins: satellite latency
+    /// floor, mobile-like variation, then throughput and latency tiers.
+    pub fn from_measurements(server_id: &str, latency: &Stats, download_mbps: f64) -> Self {
+        let (min, spread) = (latency.min, latency.stddev);
+        let mut reasons = vec![format!(
+            "minimum latency {:.0} ms, varying by {:.1} ms between probes; download {:.1} Mbps",
+            min, spread, download_mbps
+        )];
+        let (label, reason) = if min >= SATELLITE_MIN_LATENCY_MS {
+            (
+                "Satellite-class",
+                format!("latency never drops below {:.0} ms, as on geostationary links", SATELLITE_MIN_LATENCY_MS),
+            )
+        } else if spread >= MOBILE_LATENCY_SPREAD_MS {
+            (
+                "Mobile-class",
+                format!("latency varies by more than {:.0} ms, typical of cellular radio", MOBILE_LATENCY_SPREAD_MS),
+            )
+        } else if min < FIBER_MAX_LATENCY_MS && download_mbps >= FIBER_MIN_DOWNLOAD_MBPS {
+            (
+                "Fiber-class",
+                format!(
+                    "latency under {:.0} ms with at least {:.0} Mbps download",
+                    FIBER_MAX_LATENCY_MS, FIBER_MIN_DOWNLOAD_MBPS
+                ),
+            )
+        } else if download_mbps >= CABLE_MIN_DOWNLOAD_MBPS {
+            ("Cable-class", format!("steady latency with at least {:.0} Mbps download", CABLE_MIN_DOWNLOAD_MBPS))
+        } else {
+            ("DSL-class", format!("steady latency with under {:.0} Mbps download", CABLE_MIN_DOWNLOAD_MBPS))
+        };
+        reasons.push(reason);
+
+        Classification {
+            server_id: server_id.to_string(),
+            label,
+            min_latency_ms: min,
+            latency_spread_ms: spread,
+            download_speed_mbps: download_mbps,
+            reasons,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut output = format!("Connection to {}: {}", self.server_id, self.label);
+        for reason in &self.reasons {
+            output.push_str(&format!("\n  - {}", reason));
+        }
+        output
+    }
+}
+
+/// Runs the latency probes and the throughput probe against `server`. A
+/// download still running after the probe timeout is measured by what
+/// arrived until then.
+pub async fn measure(client: &Client, server: &Server) -> Result<(Stats, f64), String> {
+    let mut latencies = Vec::new();
+    for _ in 0..LATENCY_SAMPLES {
+        let start = Instant::now();
+        let response = tokio::time::timeout(PROBE_TIMEOUT, server.probe(client, &server.latency_url).send()).await;
+        if matches!(response, Ok(Ok(ref response)) if response.status().is_success()) {
+            latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+    if latencies.is_empty() {
+        return Err(format!("{} did not answer any latency probe", server.id));
+    }
+
+    let start = Instant::now();
+    let mut bytes = 0;
+    let download = async {
+        let mut response = client.get(server.download_url(DOWNLOAD_BYTES)).send().await?.error_for_status()?;
+        while let Some(chunk) = response.chunk().await? {
+            bytes += chunk.len();
+        }
+        Ok::<_, reqwest::Error>(())
+    };
+    let outcome = tokio::time::timeout(PROBE_TIMEOUT, download).await;
+    match outcome {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(format!("download probe from {} failed: {}", server.id, e)),
+        Err(_) if bytes == 0 => return Err(format!("download probe from {} timed out", server.id)),
+        Err(_) => {}
+    }
+    let mbps = bytes as f64 * 8.0 / start.elapsed().as_secs_f64().max(f64::EPSILON) / 1_000_000.0;
+    Ok((Stats::from_samples(&latencies), mbps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn label(latencies: &[f64], download_mbps: f64) -> &'static str {
+        Classification::from_measurements("test", &Stats::from_samples(latencies), download_mbps).label
+    }
+
+    #[test]
+    fn labels_follow_latency_and_throughput() {
+        assert_eq!(label(&[600.0, 620.0, 610.0], 50.0), "Satellite-class");
+        assert_eq!(label(&[40.0, 95.0, 60.0, 120.0], 30.0), "Mobile-class");
+        assert_eq!(label(&[3.0, 4.0, 3.5], 900.0), "Fiber-class");
+        assert_eq!(label(&[15.0, 16.0, 15.5], 300.0), "Cable-class");
+        assert_eq!(label(&[25.0, 27.0, 26.0], 12.0), "DSL-class");
+    }
+
+    #[test]
+    fn reasoning_explains_the_verdict() {
+        let verdict = Classification::from_measurements("test", &Stats::from_samples(&[3.0, 3.0]), 900.0);
+        assert_eq!(
+            verdict.text(),
+            "Connection to test: Fiber-class\n  \
+             - minimum latency 3 ms, varying by 0.0 ms between probes; download 900.0 Mbps\n  \
+             - latency under 10 ms with at least 200 Mbps download"
+        );
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_not_measurements() {
+        let mock = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/")).respond_with(ResponseTemplate::new(200)).mount(&mock).await;
+        Mock::given(method("GET")).and(path("/__down")).respond_with(ResponseTemplate::new(503)).mount(&mock).await;
+        let server = Server::custom(&mock.uri());
+
+        let err = measure(&Client::new(), &server).await.unwrap_err();
+        assert!(err.starts_with(&format!("download probe from {} failed", server.id)), "got {}", err);
+
+        let unreachable = Server { latency_url: format!("{}/missing", mock.uri()), ..server };
+        assert!(measure(&Client::new(), &unreachable).await.unwrap_err().ends_with("did not answer any latency probe"));
+    }
+}
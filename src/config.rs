@@ -0,0 +1,213 @@
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+use std::{ffi::OsString, fs, io::Write, path::Path};
+
+/// Opening comment of the files `save` writes.
+const HEADER: &str = "# speedtest-cli settings, written by `speedtest-cli setup`.
+# Each key is a flag without the leading --; flags given on the command line win.
+";
+
+/// One `key = value` line of a config file: the long name of a flag and the
+/// value(s) it defaults to.
+#[derive(Debug, PartialEq)]
+pub struct Setting {
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+/// Reads the settings of a TOML config file. Values are strings, numbers,
+/// booleans, or arrays of them for flags that take a list.
+pub fn parse(text: &str) -> Result<Vec<Setting>, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+        let line = e.span().map_or(1, |span| text[..span.start].lines().count().max(1));
+        format!("line {}: {}", line, e.message().trim_end().replace('\n', ", "))
+    })?;
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let values = match value {
+                toml::Value::Array(items) => {
+                    items.into_iter().map(|item| scalar(&key, item)).collect::<Result<_, _>>()?
+                }
+                value => vec![scalar(&key, value)?],
+            };
+            Ok(Setting { key, values })
+        })
+        .collect()
+}
+
+fn scalar(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Array(_) | toml::Value::Table(_) => {
+            Err(format!("{}: expected a string, number, boolean or a list of them", key))
+        }
+        value => Ok(value.to_string()),
+    }
+}
+
+/// Puts the settings into `args` as `--key=value` arguments, right after
+/// the name of the subcommand `matches` (a lenient parse of `args`) found, so
+/// clap checks them like typed flags, `requires` and `conflicts_with`
+/// included. Flags already on the command line keep their value; settings
+/// for flags of other subcommands are skipped, and keys no command has are an error.
+pub fn inject(
+    args: &mut Vec<OsString>,
+    root: &Command,
+    matches: &ArgMatches,
+    settings: &[Setting],
+) -> Result<(), String> {
+    let (target, given, at) = match matches.subcommand() {
+        Some((name, sub)) => {
+            let at = args.iter().skip(1).position(|arg| arg == name).map_or(args.len(), |i| i + 2);
+            (root.find_subcommand(name).unwrap(), sub, at)
+        }
+        None => (root, matches, args.len().min(1)),
+    };
+    let mut injected = Vec::new();
+    for setting in settings {
+        let global = || root.get_arguments().find(|arg| arg.is_global_set() && has_long(arg, &setting.key));
+        let Some(arg) = target.get_arguments().find(|arg| has_long(arg, &setting.key)).or_else(global) else {
+            if !knows(root, &setting.key) {
+                return Err(format!("unknown option '{}'", setting.key));
+            }
+            continue;
+        };
+        if given.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = split(arg, &setting.values);
+        if values.len() > 1 && !matches!(arg.get_action(), ArgAction::Append) {
+            return Err(format!("{}: takes a single value, not a list", setting.key));
+        }
+        for value in values {
+            check(arg, &setting.key, &value)?;
+            match arg.get_action() {
+                ArgAction::SetTrue if value == "true" => injected.push(format!("--{}", setting.key)),
+                ArgAction::SetTrue => {}
+                _ => injected.push(format!("--{}={}", setting.key, value)),
+            }
+        }
+    }
+    args.splice(at..at, injected.into_iter().map(OsString::from));
+    Ok(())
+}
+
+fn has_long(arg: &Arg, key: &str) -> bool {
+    arg.get_long() == Some(key)
+}
+
+/// Whether `command` or any of its subcommands has the flag `--key`.
+fn knows(command: &Command, key: &str) -> bool {
+    command.get_arguments().any(|arg| has_long(arg, key)) || command.get_subcommands().any(|sub| knows(sub, key))
+}
+
+/// Runs `value` through the flag's parser on a one-flag command, so a bad
+/// value is reported as coming from the config file.
+fn check(arg: &Arg, key: &str, value: &str) -> Result<(), String> {
+    let mut check_arg = Arg::new("value").long(key.to_string()).action(ArgAction::Set);
+    check_arg = check_arg.value_parser(arg.get_value_parser().clone());
+    if let Some(names) = arg.get_value_names() {
+        check_arg = check_arg.value_name(names[0].clone());
+    }
+    Command::new("config")
+        .arg(check_arg)
+        .try_get_matches_from(["config".to_string(), format!("--{}={}", key, value)])
+        .map(|_| ())
+        .map_err(|e| e.to_string().lines().next().unwrap_or_default().trim_start_matches("error: ").to_string())
+}
+
+/// A list flag's values, with `a,b` strings split the way the command line would.
+fn split(arg: &Arg, values: &[String]) -> Vec<String> {
+    match arg.get_value_delimiter() {
+        Some(delimiter) => values.iter().flat_map(|value| value.split(delimiter)).map(str::to_string).collect(),
+        None => values.to_vec(),
+    }
+}
+
+/// Writes `table` to `path` below a comment explaining the format. The file
+/// can hold exporter passwords, so on Unix a new one is readable only by its owner.
+pub fn save(path: &Path, table: &toml::Table) -> Result<(), String> {
+    let text = format!("{}{}", HEADER, toml::to_string(table).map_err(|e| e.to_string())?);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path).and_then(|mut file| file.write_all(text.as_bytes())).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use clap::{CommandFactory, FromArgMatches};
+    use std::time::Duration;
+
+    #[test]
+    fn settings_are_checked_like_flags_the_command_line_overrides() {
+        let settings = parse(
+            r#"
+            interval = "1h"
+            iterations = 3
+            verbose = true
+            jitter-host = ["one.example", "two.example"]
+            influxdb-v1-url = "http://influx:8086"
+            influxdb-v1-db = "speedtest"
+            "#,
+        )
+        .unwrap();
+        let parse_args = |settings: &[Setting], args: &[&str]| {
+            let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            let lenient = Cli::command().ignore_errors(true).try_get_matches_from(&args).unwrap();
+            inject(&mut args, &Cli::command(), &lenient, settings)?;
+            let matches = Cli::command().try_get_matches_from(args).map_err(|e| e.kind().to_string())?;
+            Ok::<_, String>(Cli::from_arg_matches(&matches).unwrap())
+        };
+
+        let cli = parse_args(&settings, &["speedtest-cli"]).unwrap();
+        assert_eq!(cli.run.interval, Some(Duration::from_secs(3600)));
+        assert_eq!(cli.run.iterations, 3);
+        assert!(cli.verbose);
+        assert_eq!(cli.run.jitter_host.len(), 2);
+        assert_eq!(cli.run.export.influxdb_v1_url.as_deref(), Some("http://influx:8086"));
+
+        let cli = parse_args(&settings, &["speedtest-cli", "run", "--iterations", "5", "--jitter-host", "x.example"]);
+        let Some(crate::Command::Run(run)) = cli.unwrap().command else { panic!("expected the run command") };
+        assert_eq!((run.iterations, run.interval, run.jitter_host.len()), (5, Some(Duration::from_secs(3600)), 1));
+        let cli = parse_args(&settings, &["speedtest-cli", "history", "--limit", "2"]).unwrap();
+        assert!(matches!(cli.command, Some(crate::Command::History { limit: 2, .. })));
+
+        // A setting that needs another flag is refused, as it would be on the command line.
+        let insecure = parse("insecure = true").unwrap();
+        assert!(parse_args(&insecure, &["speedtest-cli"]).is_err());
+        assert!(parse_args(&insecure, &["speedtest-cli", "--server-url", "http://lab:8080"]).unwrap().run.insecure);
+
+        let unknown = parse("no-such-flag = 1").unwrap();
+        assert_eq!(parse_args(&unknown, &["speedtest-cli"]).err().unwrap(), "unknown option 'no-such-flag'");
+        let invalid = parse("interval = \"soon\"").unwrap();
+        assert!(parse_args(&invalid, &["speedtest-cli"])
+            .err()
+            .unwrap()
+            .starts_with("invalid value 'soon' for '--interval <DURATION>'"));
+        assert!(parse("iterations = ").is_err());
+
+        // What `setup` writes for ClickHouse without a password is a complete export config.
+        let clickhouse = parse(
+            r#"
+            clickhouse-url = "http://localhost:8123"
+            clickhouse-db = "default"
+            clickhouse-user = "default"
+            clickhouse-password = ""
+            "#,
+        )
+        .unwrap();
+        let cli = parse_args(&clickhouse, &["speedtest-cli"]).unwrap();
+        assert_eq!(cli.run.export.clickhouse_password.as_deref(), Some(""));
+        assert!(crate::exporters(&cli.run.export).is_ok());
+    }
+}
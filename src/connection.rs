@@ -0,0 +1,251 @@
+use crate::dns::FallbackResolver;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tower::{Layer, Service};
+
+/// Counts the connections the client opens and how long the last one took
+/// to establish (TCP connect plus any TLS handshake).
+#[derive(Default)]
+pub struct ConnectionStats {
+    opened: AtomicU64,
+    last_connect_us: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Number of connections opened so far; pass it to `since` after a request.
+    pub fn opened(&self) -> u64 {
+        self.opened.load(Ordering::Relaxed)
+    }
+
+    /// The connect time of the last new connection if any were opened after
+    /// `opened` was sampled, or `None` when a pooled connection was reused.
+    pub fn since(&self, opened: u64) -> Option<Duration> {
+        (self.opened() > opened).then(|| Duration::from_micros(self.last_connect_us.load(Ordering::Relaxed)))
+    }
+
+    /// Describes the connection a request used, for verbose output.
+    pub fn describe(&self, opened: u64) -> String {
+        match self.since(opened) {
+            Some(connect) => format!("connect_ms={:.2}, connection_reused=false", connect.as_secs_f64() * 1000.0),
+            None => "connection_reused=true".to_string(),
+        }
+    }
+
+    fn record(&self, connect: Duration) {
+        self.last_connect_us.store(connect.as_micros() as u64, Ordering::Relaxed);
+        self.opened.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Connector layer that records every established connection in `ConnectionStats`.
+#[derive(Clone)]
+pub struct ConnectTimingLayer {
+    stats: Arc<ConnectionStats>,
+}
+
+impl ConnectTimingLayer {
+    pub fn new(stats: Arc<ConnectionStats>) -> Self {
+        ConnectTimingLayer { stats }
+    }
+}
+
+impl<S> Layer<S> for ConnectTimingLayer {
+    type Service = ConnectTiming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectTiming { inner, stats: self.stats.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectTiming<S> {
+    inner: S,
+    stats: Arc<ConnectionStats>,
+}
+
+impl<S, R> Service<R> for ConnectTiming<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let stats = self.stats.clone();
+        let connect = self.inner.call(request);
+        Box::pin(async move {
+            let start = Instant::now();
+            let conn = connect.await?;
+            stats.record(start.elapsed());
+            Ok(conn)
+        })
+    }
+}
+
+/// Where the time to the first response byte of a new connection goes
+/// (--connection-profile); the steps add up to `total_ms`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ConnectionProfile {
+    pub dns_ms: f32,
+    pub tcp_connect_ms: f32,
+    /// Absent for plain http servers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<f32>,
+    /// From sending the request to the first byte of the response.
+    pub ttfb_ms: f32,
+    pub total_ms: f32,
+}
+
+/// Connects to `url` without the client's pool, so that each step is timed
+/// on its own: a DNS lookup past the cache, the TCP connect, the TLS
+/// handshake for https, then a GET and the wait for its first response byte.
+/// The connection leaves through `interface` and `local` like the client's.
+pub async fn profile(
+    url: &str,
+    resolver: &FallbackResolver,
+    insecure: bool,
+    interface: Option<&str>,
+    local: Option<IpAddr>,
+) -> Result<ConnectionProfile, String> {
+    let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("the URL has no host")?;
+    // IPv6 literals keep their brackets in the Host header but not for TLS.
+    let name = host.trim_matches(['[', ']']);
+    let port = url.port_or_known_default().ok_or("the URL has no port")?;
+
+    let (addrs, dns) = resolver.lookup_timed(name).await.map_err(|e| e.to_string())?;
+
+    let start = Instant::now();
+    let mut tcp = Err("no addresses".to_string());
+    // A bound local address can only reach servers of its own family.
+    for addr in addrs.into_iter().filter(|addr| local.is_none_or(|local| local.is_ipv4() == addr.is_ipv4())) {
+        tcp = crate::interface::connect(SocketAddr::new(addr.ip(), port), interface, local).await;
+        if tcp.is_ok() {
+            break;
+        }
+    }
+    let tcp = tcp.map_err(|e| format!("TCP connect failed: {}", e))?;
+    let tcp_connect = start.elapsed();
+
+    let request = format!(
+        "GET {}{} HTTP/1.1\r\nHost: {}\r\nUser-Agent: speedtest-cli/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        url.path(),
+        url.query().map(|query| format!("?{}", query)).unwrap_or_default(),
+        url.port().map_or(host.to_string(), |port| format!("{}:{}", host, port)),
+        env!("CARGO_PKG_VERSION")
+    );
+    let (tls, ttfb) = match url.scheme() {
+        "https" => {
+            let connector = native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(insecure)
+                .build()
+                .map_err(|e| e.to_string())?;
+            let start = Instant::now();
+            let stream = tokio_native_tls::TlsConnector::from(connector)
+                .connect(name, tcp)
+                .await
+                .map_err(|e| format!("TLS handshake failed: {}", e))?;
+            let tls = start.elapsed();
+            (Some(tls), first_byte(stream, &request).await?)
+        }
+        _ => (None, first_byte(tcp, &request).await?),
+    };
+
+    let ms = |took: Duration| took.as_secs_f64() as f32 * 1000.0;
+    Ok(ConnectionProfile {
+        dns_ms: ms(dns),
+        tcp_connect_ms: ms(tcp_connect),
+        tls_ms: tls.map(ms),
+        ttfb_ms: ms(ttfb),
+        total_ms: ms(dns + tcp_connect + tls.unwrap_or_default() + ttfb),
+    })
+}
+
+/// Sends `request` and waits for the first byte of the response.
+async fn first_byte(mut stream: impl AsyncRead + AsyncWrite + Unpin, request: &str) -> Result<Duration, String> {
+    let start = Instant::now();
+    stream.write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+    match stream.read(&mut [0; 1]).await {
+        Ok(0) => Err("the server closed the connection without responding".to_string()),
+        Ok(_) => Ok(start.elapsed()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_new_and_reused_connections() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let stats = Arc::new(ConnectionStats::default());
+        let client =
+            reqwest::Client::builder().connector_layer(ConnectTimingLayer::new(stats.clone())).build().unwrap();
+
+        let before = stats.opened();
+        client.get(server.uri()).send().await.unwrap().bytes().await.unwrap();
+        assert!(stats.since(before).is_some());
+
+        let before = stats.opened();
+        client.get(server.uri()).send().await.unwrap().bytes().await.unwrap();
+        assert!(stats.since(before).is_none());
+        assert_eq!(stats.describe(before), "connection_reused=true");
+    }
+
+    #[tokio::test]
+    async fn profile_times_each_step_of_a_new_connection() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::path("/__down"))
+            .respond_with(wiremock::ResponseTemplate::new(200).set_delay(Duration::from_millis(100)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/__down?bytes=0", server.uri());
+        let resolver = FallbackResolver::new(false);
+        let timings = profile(&url, &resolver, false, None, None).await.unwrap();
+
+        assert_eq!(timings.tls_ms, None);
+        assert!(timings.ttfb_ms >= 100.0, "got {:?}", timings);
+        let sum = timings.dns_ms + timings.tcp_connect_ms + timings.ttfb_ms;
+        assert!((timings.total_ms - sum).abs() < 0.01, "got {:?}", timings);
+        assert!(profile("not a url", &resolver, false, None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn profile_connects_from_the_bound_address() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let resolver = FallbackResolver::new(false);
+
+        assert!(profile(&server.uri(), &resolver, false, None, Some("127.0.0.1".parse().unwrap())).await.is_ok());
+        let v6 = profile(&server.uri(), &resolver, false, None, Some("::1".parse().unwrap())).await.unwrap_err();
+        assert_eq!(v6, "TCP connect failed: no addresses");
+    }
+}
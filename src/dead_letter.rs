@@ -0,0 +1,48 @@
+use crate::{output, SpeedTestResult};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// An exporter that gave up on a result, and its last error.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Failure {
+    pub exporter: String,
+    pub error: String,
+}
+
+/// A `--dead-letter-dir` file. `results` is the key `--format json` reports
+/// use, so the `export` subcommand replays the file as it is.
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    results: [&'a SpeedTestResult; 1],
+    failed_exporters: &'a [Failure],
+}
+
+/// Writes `result` and the exports that failed for it to `<run_id>.json` in
+/// `dir`, creating the directory if needed, and returns the file's path.
+pub fn write(dir: &Path, result: &SpeedTestResult, failures: &[Failure]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", result.run_id));
+    let letter = DeadLetter { results: [result], failed_exporters: failures };
+    output::write_atomic(&path, &serde_json::to_vec_pretty(&letter).unwrap())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_letters_can_be_read_back_for_replay() {
+        let dir = std::env::temp_dir().join(format!("speedtest-dead-letter-{}", std::process::id()));
+        let result = SpeedTestResult { download_speed_mbps: 100.0, ..Default::default() };
+        let failures = [Failure { exporter: "Clickhouse".to_string(), error: "connection refused".to_string() }];
+
+        let path = write(&dir, &result, &failures).unwrap();
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(output::parse_json(&json).unwrap()[0].run_id, result.run_id);
+        let letter: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(letter["failed_exporters"][0]["error"], "connection refused");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use crate::servers::Server;
+use reqwest::Client;
+use std::time::{Duration, Instant};
+
+/// Upload payload sizes probed, in bytes, smallest first.
+const PROBE_SIZES: [u64; 7] = [1_000, 4_000, 16_000, 64_000, 256_000, 1_000_000, 4_000_000];
+/// A probe that hasn't finished after this long counts as stalled.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upload below this fraction of download is worth diagnosing.
+const ANOMALY_RATIO: f64 = 0.25;
+/// Throughput below this fraction of the best smaller probe counts as a collapse.
+const COLLAPSE_RATIO: f64 = 0.25;
+/// Size of each of the two identical `--cache-check` downloads.
+const CACHE_CHECK_BYTES: u64 = 10_000_000;
+/// Time allowed for each `--cache-check` download.
+const CACHE_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+/// A repeat download this many times faster than the first was probably
+/// served from a cache rather than the test server.
+const CACHE_SPEEDUP_RATIO: f64 = 3.0;
+
+/// Whether the upload is low enough relative to the download to suggest a
+/// path problem rather than an asymmetric plan.
+pub fn upload_is_anomalous(download_mbps: f64, upload_mbps: f64) -> bool {
+    download_mbps > 0.0 && upload_mbps < download_mbps * ANOMALY_RATIO
+}
+
+/// Uploads progressively larger payloads, returning each size with its
+/// throughput in Mbps, or `None` if it failed, stalled or was rejected (a
+/// proxy's 413, say).
+pub async fn probe_upload_sizes(client: &Client, url: &str) -> Vec<(u64, Option<f64>)> {
+    let mut samples = Vec::new();
+    for size in PROBE_SIZES {
+        let start = Instant::now();
+        let upload = client.post(url).body(vec![0u8; size as usize]).send();
+        let mbps = match tokio::time::timeout(PROBE_TIMEOUT, upload).await {
+            Ok(Ok(response)) if response.status().is_success() => {
+                Some(size as f64 * 8.0 / start.elapsed().as_secs_f64() / 1_000_000.0)
+            }
+            _ => None,
+        };
+        samples.push((size, mbps));
+        if mbps.is_none() {
+            break;
+        }
+    }
+    samples
+}
+
+/// The smallest payload size at which throughput collapsed (or the upload
+/// stalled) compared with the smaller probes before it.
+pub fn collapse_threshold(samples: &[(u64, Option<f64>)]) -> Option<u64> {
+    let mut best: f64 = 0.0;
+    for &(size, mbps) in samples {
+        match mbps {
+            None => return Some(size),
+            Some(mbps) if best > 0.0 && mbps < best * COLLAPSE_RATIO => return Some(size),
+            Some(mbps) => best = best.max(mbps),
+        }
+    }
+    None
+}
+
+/// Downloads the same payload from `server` twice, returning the throughput
+/// of each attempt in Mbps, or `None` if either failed or timed out.
+pub async fn probe_repeat_download(client: &Client, server: &Server) -> Option<(f64, f64)> {
+    let url = server.download_url(CACHE_CHECK_BYTES);
+    let first = timed_download(client, &url).await?;
+    let second = timed_download(client, &url).await?;
+    Some((first, second))
+}
+
+async fn timed_download(client: &Client, url: &str) -> Option<f64> {
+    let start = Instant::now();
+    let download = async { client.get(url).send().await?.error_for_status()?.bytes().await };
+    let body = tokio::time::timeout(CACHE_CHECK_TIMEOUT, download).await.ok()?.ok()?;
+    Some(body.len() as f64 * 8.0 / start.elapsed().as_secs_f64().max(f64::EPSILON) / 1_000_000.0)
+}
+
+/// Whether the second of two identical downloads sped up enough to suggest
+/// a cache in the path answered it.
+pub fn probably_cached(first_mbps: f64, second_mbps: f64) -> bool {
+    first_mbps > 0.0 && second_mbps >= first_mbps * CACHE_SPEEDUP_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_low_upload_relative_to_download_is_anomalous() {
+        assert!(upload_is_anomalous(500.0, 20.0));
+        assert!(!upload_is_anomalous(500.0, 200.0));
+        assert!(!upload_is_anomalous(0.0, 0.0));
+    }
+
+    #[test]
+    fn only_a_large_speedup_on_the_repeat_suggests_caching() {
+        assert!(probably_cached(50.0, 400.0));
+        assert!(!probably_cached(50.0, 70.0));
+        assert!(!probably_cached(0.0, 400.0));
+    }
+
+    #[test]
+    fn finds_the_size_where_throughput_collapses() {
+        let healthy = [(1_000, Some(1.0)), (4_000, Some(4.0)), (16_000, Some(12.0)), (64_000, Some(40.0))];
+        assert_eq!(collapse_threshold(&healthy), None);
+
+        let collapsed = [(1_000, Some(1.0)), (4_000, Some(4.0)), (16_000, Some(12.0)), (64_000, Some(0.5))];
+        assert_eq!(collapse_threshold(&collapsed), Some(64_000));
+
+        let stalled = [(1_000, Some(1.0)), (4_000, None)];
+        assert_eq!(collapse_threshold(&stalled), Some(4_000));
+    }
+
+    #[tokio::test]
+    async fn rejected_uploads_are_a_collapse() {
+        use wiremock::{matchers::method, Mock, MockServer, Request, ResponseTemplate};
+        let mock = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(|request: &Request| request.body.len() > 100_000)
+            .respond_with(ResponseTemplate::new(413))
+            .mount(&mock)
+            .await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(200)).mount(&mock).await;
+
+        let samples = probe_upload_sizes(&Client::new(), &mock.uri()).await;
+
+        assert_eq!(samples.last(), Some(&(256_000, None)));
+        assert_eq!(collapse_threshold(&samples), Some(256_000));
+    }
+}
@@ -0,0 +1,181 @@
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Pause before the second attempt with the system resolver.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How long a resolved host is reused. Every phase of a run connects within
+/// it, while `--interval` cycles still pick up DNS changes.
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+type Cache = Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>;
+
+/// Resolves with the system resolver, retrying once so a momentary DNS
+/// hiccup doesn't fail the run, then optionally asks Cloudflare's public
+/// resolver (`--dns-fallback`). Answers are cached for `CACHE_TTL`, so new
+/// connections of later phases don't pay for DNS again.
+pub struct FallbackResolver {
+    fallback: Option<Arc<TokioAsyncResolver>>,
+    cache: Arc<Cache>,
+}
+
+impl FallbackResolver {
+    pub fn new(fallback: bool) -> Self {
+        let fallback = fallback
+            .then(|| Arc::new(TokioAsyncResolver::tokio(ResolverConfig::cloudflare(), ResolverOpts::default())));
+        FallbackResolver { fallback, cache: Arc::default() }
+    }
+
+    /// Resolves `host` into the cache ahead of the first request and returns
+    /// how long the lookup took.
+    pub async fn warm(&self, host: &str) -> Result<Duration, DnsError> {
+        let start = Instant::now();
+        lookup(host.to_string(), self.fallback.clone(), self.cache.clone()).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Resolves `host` through the cache, as the client's connections do.
+    pub async fn addresses(&self, host: &str) -> Result<Vec<SocketAddr>, DnsError> {
+        lookup(host.to_string(), self.fallback.clone(), self.cache.clone()).await
+    }
+
+    /// Resolves `host` past the cache, which it refreshes, and returns the
+    /// addresses with how long the lookup took.
+    pub async fn lookup_timed(&self, host: &str) -> Result<(Vec<SocketAddr>, Duration), DnsError> {
+        let start = Instant::now();
+        let addrs = resolve_uncached(host, self.fallback.clone()).await?;
+        let took = start.elapsed();
+        self.cache.lock().unwrap().insert(host.to_string(), (Instant::now(), addrs.clone()));
+        Ok((addrs, took))
+    }
+}
+
+/// A failed DNS lookup, kept distinct so it isn't reported as the server
+/// refusing connections.
+#[derive(Debug)]
+pub struct DnsError {
+    host: String,
+    reason: String,
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DNS lookup for {} failed: {}", self.host, self.reason)
+    }
+}
+
+impl Error for DnsError {}
+
+impl Resolve for FallbackResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let lookup = lookup(name.as_str().to_string(), self.fallback.clone(), self.cache.clone());
+        Box::pin(async move {
+            match lookup.await {
+                Ok(addrs) => Ok(Box::new(addrs.into_iter()) as Addrs),
+                Err(e) => Err(Box::new(e) as Box<dyn Error + Send + Sync>),
+            }
+        })
+    }
+}
+
+async fn lookup(
+    host: String,
+    fallback: Option<Arc<TokioAsyncResolver>>,
+    cache: Arc<Cache>,
+) -> Result<Vec<SocketAddr>, DnsError> {
+    if let Some((resolved, addrs)) = cache.lock().unwrap().get(&host) {
+        if resolved.elapsed() < CACHE_TTL {
+            return Ok(addrs.clone());
+        }
+    }
+    let addrs = resolve_uncached(&host, fallback).await?;
+    cache.lock().unwrap().insert(host, (Instant::now(), addrs.clone()));
+    Ok(addrs)
+}
+
+async fn resolve_uncached(host: &str, fallback: Option<Arc<TokioAsyncResolver>>) -> Result<Vec<SocketAddr>, DnsError> {
+    let mut reason = match system_lookup(host).await {
+        Ok(addrs) => return Ok(addrs),
+        Err(_) => {
+            tokio::time::sleep(RETRY_DELAY).await;
+            match system_lookup(host).await {
+                Ok(addrs) => return Ok(addrs),
+                Err(e) => e.to_string(),
+            }
+        }
+    };
+    if let Some(fallback) = fallback {
+        match fallback.lookup_ip(host).await {
+            Ok(ips) => return Ok(ips.iter().map(|ip| SocketAddr::new(ip, 0)).collect()),
+            Err(e) => reason = format!("{}; fallback resolver: {}", reason, e),
+        }
+    }
+    Err(DnsError { host: host.to_string(), reason })
+}
+
+async fn system_lookup(host: &str) -> std::io::Result<Vec<SocketAddr>> {
+    Ok(tokio::net::lookup_host((host, 0)).await?.collect())
+}
+
+/// `error` for the user, naming a DNS failure as such instead of the generic
+/// "error sending request".
+pub fn describe(error: &reqwest::Error) -> String {
+    let mut source = error.source();
+    while let Some(cause) = source {
+        if let Some(dns) = cause.downcast_ref::<DnsError>() {
+            return dns.to_string();
+        }
+        source = cause.source();
+    }
+    match error.is_connect() {
+        true => format!("connection failed: {}", error),
+        false => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dns_failures_are_reported_as_such() {
+        let client = reqwest::Client::builder().dns_resolver(Arc::new(FallbackResolver::new(false))).build().unwrap();
+        let error = client.get("http://speedtest-cli.invalid/").send().await.unwrap_err();
+        assert!(describe(&error).starts_with("DNS lookup for speedtest-cli.invalid failed"), "{}", describe(&error));
+    }
+
+    #[tokio::test]
+    async fn warmed_hosts_are_answered_from_the_cache() {
+        let resolver = FallbackResolver::new(false);
+        resolver.warm("localhost").await.unwrap();
+        let cached = resolver.cache.lock().unwrap().get("localhost").map(|(_, addrs)| addrs.clone()).unwrap();
+        assert!(!cached.is_empty());
+
+        // A poisoned entry proves later lookups don't go back to the system resolver.
+        let marker: SocketAddr = "192.0.2.1:0".parse().unwrap();
+        resolver.cache.lock().unwrap().insert("localhost".to_string(), (Instant::now(), vec![marker]));
+        let addrs: Vec<SocketAddr> = resolver.resolve("localhost".parse().unwrap()).await.unwrap().collect();
+        assert_eq!(addrs, [marker]);
+    }
+
+    #[tokio::test]
+    async fn refused_connections_are_not_dns_failures() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let client = reqwest::Client::builder().dns_resolver(Arc::new(FallbackResolver::new(false))).build().unwrap();
+        let error = client.get(format!("http://localhost:{}/", port)).send().await.unwrap_err();
+        assert!(describe(&error).starts_with("connection failed"), "{}", describe(&error));
+    }
+}
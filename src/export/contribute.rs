@@ -0,0 +1,94 @@
+use super::{ExportError, Exporter};
+use crate::SpeedTestResult;
+use async_trait::async_trait;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Time allowed for a contribution; a slow aggregator must not hold up the run.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts an anonymized copy of each result to a community aggregator
+/// (`--contribute`).
+pub struct ContributeExporter {
+    pub endpoint: String,
+}
+
+/// What a contribution contains, and nothing more: the speeds, the hour the
+/// test ran and the Cloudflare location that served it. No run id, client
+/// address or custom server URL is sent.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Contribution {
+    /// Start of the hour the test ran in.
+    pub hour: DateTime<Utc>,
+    pub download_speed_mbps: f32,
+    pub upload_speed_mbps: f32,
+    pub ping_ms: f32,
+    pub jitter_ms: f32,
+    /// Cloudflare data center code (e.g. `SFO`); absent for custom servers,
+    /// whose URLs may name private hosts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colo: Option<String>,
+}
+
+impl Contribution {
+    pub fn from_result(result: &SpeedTestResult) -> Self {
+        Contribution {
+            hour: result.timestamp.duration_trunc(TimeDelta::hours(1)).unwrap_or(result.timestamp),
+            download_speed_mbps: result.download_speed_mbps,
+            upload_speed_mbps: result.upload_speed_mbps,
+            ping_ms: result.ping_ms,
+            jitter_ms: result.jitter_ms,
+            colo: result.server_id.strip_prefix("cloudflare-").map(str::to_string),
+        }
+    }
+}
+
+#[async_trait]
+impl Exporter for ContributeExporter {
+    fn name(&self) -> &str {
+        "contribution endpoint"
+    }
+
+    async fn export(&self, result: &SpeedTestResult) -> Result<(), ExportError> {
+        reqwest::Client::new()
+            .post(&self.endpoint)
+            .timeout(TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&Contribution::from_result(result))?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn contributions_carry_only_the_anonymized_fields() {
+        let mock = MockServer::start().await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(204)).mount(&mock).await;
+        let result = SpeedTestResult {
+            timestamp: "2024-05-01T12:34:56Z".parse().unwrap(),
+            server_id: "cloudflare-SFO".to_string(),
+            download_speed_mbps: 250.0,
+            ..Default::default()
+        };
+
+        ContributeExporter { endpoint: mock.uri() }.export(&result).await.unwrap();
+
+        let requests = mock.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let fields: Vec<&String> = body.as_object().unwrap().keys().collect();
+        assert_eq!(fields, ["hour", "download_speed_mbps", "upload_speed_mbps", "ping_ms", "jitter_ms", "colo"]);
+        assert_eq!(body["hour"], "2024-05-01T12:00:00Z");
+        assert_eq!(body["colo"], "SFO");
+
+        let custom = SpeedTestResult { server_id: "http://10.0.0.5:8080".to_string(), ..result };
+        assert_eq!(Contribution::from_result(&custom).colo, None);
+    }
+}
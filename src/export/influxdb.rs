@@ -0,0 +1,65 @@
+use super::{ExportError, Exporter};
+use crate::{output, SpeedTestResult};
+use async_trait::async_trait;
+
+/// Writes results as line protocol to an InfluxDB 1.x `/write` endpoint.
+pub struct InfluxV1Exporter {
+    pub url: String,
+    pub database: String,
+    /// Basic auth credentials, for servers with authentication enabled.
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+#[async_trait]
+impl Exporter for InfluxV1Exporter {
+    fn name(&self) -> &str {
+        "InfluxDB"
+    }
+
+    async fn export(&self, result: &SpeedTestResult) -> Result<(), ExportError> {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/write", self.url.trim_end_matches('/')))
+            .query(&[("db", self.database.as_str()), ("precision", "ns")])
+            .body(output::influx_line(result));
+        if let Some(user) = &self.user {
+            request = request.basic_auth(user, self.password.as_ref());
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[tokio::test]
+    async fn results_are_written_to_the_database_with_basic_auth() {
+        let mock = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/write"))
+            .and(query_param("db", "speed"))
+            .and(header("authorization", "Basic YWRtaW46c2VjcmV0"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock)
+            .await;
+        let result = SpeedTestResult { server_id: "cloudflare".to_string(), ..Default::default() };
+        let exporter = InfluxV1Exporter {
+            url: format!("{}/", mock.uri()),
+            database: "speed".to_string(),
+            user: Some("admin".to_string()),
+            password: Some("secret".to_string()),
+        };
+
+        exporter.export(&result).await.unwrap();
+
+        let requests = mock.received_requests().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&requests[0].body), output::influx_line(&result));
+    }
+}
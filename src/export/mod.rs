@@ -0,0 +1,66 @@
+mod clickhouse;
+mod contribute;
+mod influxdb;
+
+pub use self::clickhouse::{ClickhouseDsn, ClickhouseExporter};
+pub use self::contribute::ContributeExporter;
+pub use self::influxdb::InfluxV1Exporter;
+
+use crate::SpeedTestResult;
+use async_trait::async_trait;
+
+/// An export backend compiled into this build, as shown by `--list-exporters`.
+pub struct Backend {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Flags that must all be given for the backend to be enabled.
+    pub required: &'static str,
+    pub optional: &'static str,
+}
+
+pub const BACKENDS: [Backend; 3] = [
+    Backend {
+        name: "clickhouse",
+        description: "Inserts each result into an internet_speed table over HTTP",
+        required: "--clickhouse-url, --clickhouse-user, --clickhouse-password (or a --clickhouse-dsn with all three)",
+        optional: "--clickhouse-db, --clickhouse-no-create-table, --clickhouse-compression",
+    },
+    Backend {
+        name: "influxdb-v1",
+        description: "Writes each result as line protocol to an InfluxDB 1.x /write endpoint",
+        required: "--influxdb-v1-url, --influxdb-v1-db",
+        optional: "--influxdb-v1-user, --influxdb-v1-password",
+    },
+    Backend {
+        name: "contribute",
+        description: "Opt-in: posts an anonymized copy of each result to a community aggregator",
+        required: "--contribute, --telemetry-endpoint",
+        optional: "none",
+    },
+];
+
+/// The `--list-exporters` listing.
+pub fn describe_backends() -> String {
+    BACKENDS
+        .iter()
+        .map(|backend| {
+            format!(
+                "{}: {}\n  required: {}\n  optional: {}",
+                backend.name, backend.description, backend.required, backend.optional
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub type ExportError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A destination results are pushed to after every run. Adding a backend
+/// means implementing this trait and registering it in `main`.
+#[async_trait]
+pub trait Exporter: Send + Sync {
+    /// Human-readable backend name used in log messages.
+    fn name(&self) -> &str;
+
+    async fn export(&self, result: &SpeedTestResult) -> Result<(), ExportError>;
+}
@@ -0,0 +1,201 @@
+use crate::{output, SpeedTestResult};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction, TransactionBehavior};
+use std::{path::Path, time::Duration};
+
+/// How long a write waits for an overlapping run to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A result as stored in the history database.
+#[derive(Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub server_id: String,
+    pub download_speed_mbps: f64,
+    pub upload_speed_mbps: f64,
+    pub ping_ms: f64,
+    pub jitter_ms: f64,
+}
+
+impl HistoryEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(HistoryEntry {
+            timestamp: row.get(0)?,
+            server_id: row.get(1)?,
+            download_speed_mbps: row.get(2)?,
+            upload_speed_mbps: row.get(3)?,
+            ping_ms: row.get(4)?,
+            jitter_ms: row.get(5)?,
+        })
+    }
+}
+
+const COLUMNS: &str = "timestamp, server_id, download_speed_mbps, upload_speed_mbps, ping_ms, jitter_ms";
+
+/// Local SQLite store of past results, used to spot regressions between runs.
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Opens the database in WAL mode, so an overlapping scheduled run
+    /// waits for the write lock instead of failing with "database is locked".
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    fn in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS results (
+                run_id TEXT PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                server_id TEXT NOT NULL,
+                download_speed_mbps REAL NOT NULL,
+                upload_speed_mbps REAL NOT NULL,
+                ping_ms REAL NOT NULL,
+                jitter_ms REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS results_server_time ON results (server_id, timestamp);",
+        )?;
+        Ok(History { conn })
+    }
+
+    pub fn insert(&self, result: &SpeedTestResult) -> rusqlite::Result<()> {
+        // Taking the write lock up front makes a concurrent writer wait out the
+        // busy timeout rather than fail halfway through.
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        tx.execute(
+            &format!("INSERT OR REPLACE INTO results (run_id, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)", COLUMNS),
+            params![
+                result.run_id.to_string(),
+                result.timestamp,
+                result.server_id,
+                result.download_speed_mbps as f64,
+                result.upload_speed_mbps as f64,
+                result.ping_ms as f64,
+                result.jitter_ms as f64,
+            ],
+        )?;
+        tx.commit()
+    }
+
+    /// The most recent stored result for the same server, so switching
+    /// endpoints never compares unrelated measurements.
+    pub fn compare_with_previous(&self, result: &SpeedTestResult) -> rusqlite::Result<Option<HistoryEntry>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM results WHERE server_id = ?1 AND run_id != ?2 ORDER BY timestamp DESC LIMIT 1",
+                    COLUMNS
+                ),
+                params![result.server_id, result.run_id.to_string()],
+                HistoryEntry::from_row,
+            )
+            .optional()
+    }
+
+    /// Stored results, newest first, optionally limited to one server.
+    pub fn list(&self, server_id: Option<&str>, limit: u32) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM results WHERE ?1 IS NULL OR server_id = ?1 ORDER BY timestamp DESC LIMIT ?2",
+            COLUMNS
+        ))?;
+        let entries = stmt.query_map(params![server_id, limit], HistoryEntry::from_row)?;
+        entries.collect()
+    }
+}
+
+/// Describes how `result` changed relative to `previous`, timed in `timezone`.
+pub fn describe_change(result: &SpeedTestResult, previous: &HistoryEntry, timezone: Tz) -> String {
+    let change = |now: f32, before: f64| {
+        if before == 0.0 {
+            "n/a".to_string()
+        } else {
+            format!("{:+.1}%", (now as f64 - before) / before * 100.0)
+        }
+    };
+    format!(
+        "Compared with {} on {}: download {}, upload {}, ping {}",
+        output::format_time(previous.timestamp, timezone),
+        previous.server_id,
+        change(result.download_speed_mbps, previous.download_speed_mbps),
+        change(result.upload_speed_mbps, previous.upload_speed_mbps),
+        change(result.ping_ms, previous.ping_ms),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn result(server_id: &str, minutes_ago: i64, download: f32) -> SpeedTestResult {
+        SpeedTestResult {
+            timestamp: Utc::now() - chrono::Duration::minutes(minutes_ago),
+            run_id: Uuid::new_v4(),
+            server_id: server_id.to_string(),
+            download_speed_mbps: download,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn previous_result_is_per_server() {
+        let history = History::in_memory().unwrap();
+        history.insert(&result("cloudflare", 20, 100.0)).unwrap();
+        history.insert(&result("lab", 10, 900.0)).unwrap();
+
+        let current = result("cloudflare", 0, 95.0);
+        let previous = history.compare_with_previous(&current).unwrap().unwrap();
+        assert_eq!(previous.server_id, "cloudflare");
+        assert_eq!(previous.download_speed_mbps, 100.0);
+        assert!(history.compare_with_previous(&result("other", 0, 1.0)).unwrap().is_none());
+        assert!(describe_change(&current, &previous, Tz::UTC).contains("download -5.0%"));
+    }
+
+    #[test]
+    fn overlapping_writers_wait_for_the_lock() {
+        let dir = std::env::temp_dir().join(format!("speedtest-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.db");
+        let (first, second) = (History::open(&path).unwrap(), History::open(&path).unwrap());
+
+        first.conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            first.conn.execute_batch("COMMIT").unwrap();
+        });
+        second.insert(&result("cloudflare", 0, 1.0)).unwrap();
+        release.join().unwrap();
+
+        assert_eq!(second.list(None, 10).unwrap().len(), 1);
+        drop(second);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn list_filters_by_server_newest_first() {
+        let history = History::in_memory().unwrap();
+        history.insert(&result("cloudflare", 20, 1.0)).unwrap();
+        history.insert(&result("lab", 10, 2.0)).unwrap();
+        history.insert(&result("cloudflare", 5, 3.0)).unwrap();
+
+        let all = history.list(None, 10).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].download_speed_mbps, 3.0);
+
+        let lab = history.list(Some("lab"), 10).unwrap();
+        assert_eq!(lab.len(), 1);
+        assert_eq!(lab[0].server_id, "lab");
+        assert_eq!(history.list(Some("cloudflare"), 1).unwrap().len(), 1);
+    }
+}
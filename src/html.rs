@@ -0,0 +1,186 @@
+use crate::{
+    output::{format_time, OutputOptions},
+    summary::Summary,
+    SpeedTestResult,
+};
+
+/// Chart size in SVG user units; the page scales it to its width.
+const CHART_WIDTH: f64 = 600.0;
+const CHART_HEIGHT: f64 = 200.0;
+
+/// What a connection is good for, in words for non-technical readers.
+/// Thresholds follow common streaming and video-call recommendations.
+pub fn verdict(download_mbps: f64, upload_mbps: f64, ping_ms: f64, jitter_ms: f64) -> (&'static str, &'static str) {
+    if download_mbps <= 0.0 && upload_mbps <= 0.0 {
+        ("No result", "The test could not measure the connection.")
+    } else if download_mbps >= 100.0 && upload_mbps >= 20.0 && ping_ms <= 30.0 && jitter_ms <= 10.0 {
+        ("Excellent", "Plenty for 4K streaming, large downloads and video calls on several devices at once.")
+    } else if download_mbps >= 25.0 && upload_mbps >= 5.0 && ping_ms <= 60.0 {
+        ("Good", "Fine for HD streaming and video calls.")
+    } else if download_mbps >= 5.0 && ping_ms <= 150.0 {
+        ("Fair", "Fine for browsing and standard-definition video; video calls may stutter.")
+    } else {
+        ("Poor", "Expect slow page loads and buffering video.")
+    }
+}
+
+/// A self-contained HTML page (inline CSS, no scripts or external assets)
+/// with the results, a verdict per server and, for several iterations, an
+/// inline SVG chart of the speeds.
+pub fn report(results: &[SpeedTestResult], summaries: &[Summary], options: &OutputOptions) -> String {
+    let unit = options.unit;
+    let mut body = String::new();
+    for summary in summaries {
+        body.push_str(&format!("<h2>{}</h2>\n", escape(&summary.server_id)));
+        if !options.ping_only {
+            let (label, explanation) = verdict(
+                summary.download_speed_mbps.mean,
+                summary.upload_speed_mbps.mean,
+                summary.ping_ms.mean,
+                summary.jitter_ms.mean,
+            );
+            body.push_str(&format!(
+                "<p class=\"verdict {}\"><strong>{}</strong> {}</p>\n",
+                label.to_lowercase().replace(' ', "-"),
+                label,
+                explanation
+            ));
+        }
+
+        let runs: Vec<&SpeedTestResult> = results.iter().filter(|r| r.server_id == summary.server_id).collect();
+        body.push_str("<table>\n<tr><th>Time</th>");
+        if !options.ping_only {
+            body.push_str(&format!("<th>Download ({0})</th><th>Upload ({0})</th>", unit.label()));
+        }
+        body.push_str("<th>Ping (ms)</th>");
+        if !options.ping_only {
+            body.push_str("<th>Jitter (ms)</th>");
+        }
+        body.push_str("</tr>\n");
+        for run in &runs {
+            body.push_str(&format!("<tr><td>{}</td>", escape(&format_time(run.timestamp, options.timezone))));
+            if !options.ping_only {
+                body.push_str(&format!(
+                    "<td>{:.2}</td><td>{:.2}</td>",
+                    unit.convert(run.download_speed_mbps as f64),
+                    unit.convert(run.upload_speed_mbps as f64)
+                ));
+            }
+            body.push_str(&format!("<td>{:.0}</td>", run.ping_ms));
+            if !options.ping_only {
+                body.push_str(&format!("<td>{:.2}</td>", run.jitter_ms));
+            }
+            body.push_str("</tr>\n");
+        }
+        body.push_str("</table>\n");
+        if runs.len() > 1 && !options.ping_only {
+            let series = |metric: fn(&SpeedTestResult) -> f32| -> Vec<f64> {
+                runs.iter().map(|run| unit.convert(metric(run) as f64)).collect()
+            };
+            body.push_str(&chart(&series(|r| r.download_speed_mbps), &series(|r| r.upload_speed_mbps), unit.label()));
+        }
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>Internet speed test</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 44rem; margin: 2rem auto; padding: 0 1rem; color: #222; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ padding: 0.4rem 0.6rem; border-bottom: 1px solid #ddd; text-align: right; }}
+th:first-child, td:first-child {{ text-align: left; }}
+.verdict {{ padding: 0.6rem 0.8rem; border-radius: 0.4rem; background: #eee; }}
+.excellent {{ background: #d7f5dd; }} .good {{ background: #e3f0fb; }}
+.fair {{ background: #fdf3d3; }} .poor, .no-result {{ background: #fbdcdc; }}
+svg {{ width: 100%; height: auto; margin-top: 1rem; }}
+</style>
+</head>
+<body>
+<h1>Internet speed test</h1>
+{}</body>
+</html>
+",
+        body
+    )
+}
+
+/// Download and upload per iteration as two polylines, scaled to the fastest.
+fn chart(download: &[f64], upload: &[f64], unit: &str) -> String {
+    let max = download.iter().chain(upload).copied().fold(0.0, f64::max).max(f64::EPSILON);
+    let points = |values: &[f64]| -> String {
+        let step = CHART_WIDTH / (values.len() - 1) as f64;
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("{:.1},{:.1}", i as f64 * step, CHART_HEIGHT - value / max * CHART_HEIGHT))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    format!(
+        "<svg viewBox=\"-10 -20 {view_width} {view_height}\" role=\"img\" aria-label=\"Speed per iteration\">
+<text x=\"0\" y=\"-6\" font-size=\"12\">{max:.0} {unit} max; <tspan fill=\"#1f77b4\">download</tspan>, \
+<tspan fill=\"#ff7f0e\">upload</tspan></text>
+<line x1=\"0\" y1=\"{CHART_HEIGHT}\" x2=\"{CHART_WIDTH}\" y2=\"{CHART_HEIGHT}\" stroke=\"#999\"/>
+<polyline fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"2\" points=\"{}\"/>
+<polyline fill=\"none\" stroke=\"#ff7f0e\" stroke-width=\"2\" points=\"{}\"/>
+</svg>
+",
+        points(download),
+        points(upload),
+        view_width = CHART_WIDTH + 20.0,
+        view_height = CHART_HEIGHT + 30.0,
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{render, CsvLayout, OutputFormat, SpeedUnit};
+    use chrono_tz::Tz;
+
+    #[test]
+    fn report_is_standalone_and_charts_iterations() {
+        let options = OutputOptions {
+            summary_only: false,
+            ping_only: false,
+            unit: SpeedUnit::Mbps,
+            csv_header: true,
+            csv_columns: None,
+            csv_delimiter: b',',
+            csv_layout: CsvLayout::Long,
+            csv_units: false,
+            timezone: Tz::UTC,
+            regions: false,
+            oneline_fields: Vec::new(),
+        };
+        let run = |download: f32| SpeedTestResult {
+            server_id: "<lab>".to_string(),
+            download_speed_mbps: download,
+            upload_speed_mbps: 30.0,
+            ping_ms: 12.0,
+            jitter_ms: 2.0,
+            ..Default::default()
+        };
+
+        let single = render(OutputFormat::Html, &[run(250.0)], &options);
+        assert!(single.starts_with("<!DOCTYPE html>"));
+        assert!(single.contains("<h2>&lt;lab&gt;</h2>"));
+        assert!(single.contains("<strong>Excellent</strong>"));
+        assert!(single.contains("<td>250.00</td>"));
+        assert!(!single.contains("<svg"));
+        assert!(!single.contains("<script") && !single.contains("src="));
+
+        let series = render(OutputFormat::Html, &[run(250.0), run(10.0)], &options);
+        assert!(series.contains("<svg") && series.contains("points=\"0.0,0.0 600.0,192.0\""));
+        assert_eq!(verdict(40.0, 8.0, 45.0, 5.0).0, "Good");
+        assert_eq!(verdict(3.0, 1.0, 200.0, 40.0).0, "Poor");
+    }
+}
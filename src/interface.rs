@@ -0,0 +1,93 @@
+use reqwest::ClientBuilder;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Pins every connection of `builder` to `interface` (`--interface`) and/or
+/// the local `address` (`--bind-address`), so a test through a `wg0` tunnel
+/// measures the tunnel rather than whatever the routing table prefers.
+///
+/// The interface is bound with `SO_BINDTODEVICE` on Linux (`IP_BOUND_IF` on
+/// macOS), which holds even when the default route points elsewhere.
+pub fn bind(builder: ClientBuilder, interface: Option<&str>, address: Option<IpAddr>) -> Result<ClientBuilder, String> {
+    let builder = match address {
+        Some(address) => builder.local_address(address),
+        None => builder,
+    };
+    match interface {
+        Some(interface) => bind_device(builder, interface),
+        None => Ok(builder),
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux", target_os = "macos", target_os = "ios"))]
+fn bind_device(builder: ClientBuilder, interface: &str) -> Result<ClientBuilder, String> {
+    // Without this check a typo only shows up as every request failing to connect.
+    #[cfg(target_os = "linux")]
+    if !std::path::Path::new("/sys/class/net").join(interface).exists() {
+        return Err(format!("no network interface named {}", interface));
+    }
+    Ok(builder.interface(interface))
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+fn bind_device(_builder: ClientBuilder, _interface: &str) -> Result<ClientBuilder, String> {
+    Err("--interface is not supported on this platform; use --bind-address".to_string())
+}
+
+/// Opens a TCP connection to `addr` that leaves through `interface` and
+/// `address` like the client's, for the connections the tool opens itself.
+pub async fn connect(addr: SocketAddr, interface: Option<&str>, address: Option<IpAddr>) -> Result<TcpStream, String> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .map_err(|e| e.to_string())?;
+    bind_socket(&socket, interface, address)?;
+    socket.connect(addr).await.map_err(|e| e.to_string())
+}
+
+/// Pins `socket` the way `bind` pins the client's connections.
+fn bind_socket(socket: &TcpSocket, interface: Option<&str>, address: Option<IpAddr>) -> Result<(), String> {
+    if let Some(address) = address {
+        socket.bind(SocketAddr::new(address, 0)).map_err(|e| format!("cannot bind to {}: {}", address, e))?;
+    }
+    match interface {
+        Some(interface) => bind_socket_device(socket, interface),
+        None => Ok(()),
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_socket_device(socket: &TcpSocket, interface: &str) -> Result<(), String> {
+    socket.bind_device(Some(interface.as_bytes())).map_err(|e| format!("cannot bind to {}: {}", interface, e))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_socket_device(_socket: &TcpSocket, _interface: &str) -> Result<(), String> {
+    Err("the tool's own connections can't use --interface on this platform; use --bind-address".to_string())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn requests_go_out_of_the_bound_interface() {
+        let mock = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).mount(&mock).await;
+
+        let loopback = bind(Client::builder(), Some("lo"), Some("127.0.0.1".parse().unwrap())).unwrap();
+        assert!(loopback.build().unwrap().get(mock.uri()).send().await.unwrap().status().is_success());
+
+        let err = bind(Client::builder(), Some("speedtest-no-such0"), None).unwrap_err();
+        assert_eq!(err, "no network interface named speedtest-no-such0");
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+/// Installs the Ctrl-C handler. The first Ctrl-C asks the running phase to
+/// stop and skips the remaining ones; a second Ctrl-C exits immediately.
+pub fn install() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        NOTIFY.notify_waiters();
+        eprintln!("Interrupted, reporting partial results (press Ctrl-C again to exit)");
+
+        let _ = tokio::signal::ctrl_c().await;
+        std::process::exit(130);
+    });
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Completes once the run has been interrupted.
+pub async fn wait() {
+    let notified = NOTIFY.notified();
+    if is_interrupted() {
+        return;
+    }
+    notified.await;
+}
@@ -0,0 +1,1249 @@
+use crate::{
+    avro, connection::ConnectionProfile, html, phase::PhaseResult, protobuf, summary::Summary, SpeedTestResult,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{fs, io::Write, path::Path};
+
+/// Unit used when displaying speeds. Results are always measured in Mbps.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SpeedUnit {
+    /// Megabits per second
+    Mbps,
+    /// Mebibits per second
+    Mibps,
+    /// Megabytes per second
+    Mbyteps,
+    /// Gigabits per second
+    Gbps,
+}
+
+impl SpeedUnit {
+    pub fn convert(self, mbps: f64) -> f64 {
+        match self {
+            SpeedUnit::Mbps => mbps,
+            SpeedUnit::Mibps => mbps * 1_000_000.0 / (1024.0 * 1024.0),
+            SpeedUnit::Mbyteps => mbps / 8.0,
+            SpeedUnit::Gbps => mbps / 1000.0,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SpeedUnit::Mbps => "Mbps",
+            SpeedUnit::Mibps => "Mibps",
+            SpeedUnit::Mbyteps => "MB/s",
+            SpeedUnit::Gbps => "Gbps",
+        }
+    }
+
+    /// The unit as a column name suffix, e.g. `download_speed_mbyteps`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            SpeedUnit::Mbps => "mbps",
+            SpeedUnit::Mibps => "mibps",
+            SpeedUnit::Mbyteps => "mbyteps",
+            SpeedUnit::Gbps => "gbps",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    Text,
+    Json,
+    /// One compact JSON object per line, flushed after every cycle
+    Ndjson,
+    Yaml,
+    Csv,
+    /// InfluxDB annotated CSV, ready for `influx write`
+    InfluxAnnotatedCsv,
+    /// InfluxDB line protocol, as InfluxDB 1.x `/write` and 2.x accept it
+    #[value(alias = "influx-v1")]
+    InfluxLine,
+    /// GitHub-flavored markdown table
+    Markdown,
+    /// Time series for the Grafana JSON API datasource
+    GrafanaJson,
+    /// Length-delimited protobuf messages (proto/speedtest.proto)
+    Protobuf,
+    /// One terse line per result for status bars, e.g. `↓235 ↑40 ⟳12ms`
+    Oneline,
+    /// `KEY=value` lines to `eval` in a shell, e.g. `DOWNLOAD_MBPS=235.4`
+    Env,
+    /// A self-contained HTML page with a verdict and, for iterations, a chart
+    Html,
+    /// XML property list, for macOS tooling
+    Plist,
+    /// Avro object container file (schema fingerprint in the file metadata)
+    Avro,
+}
+
+/// How `--format csv` arranges several iterations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CsvLayout {
+    /// One row per result
+    Long,
+    /// One row per server, with the metrics of each iteration as columns
+    Wide,
+}
+
+/// A metric shown by `--format oneline`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OnelineField {
+    Download,
+    Upload,
+    Ping,
+    Jitter,
+    Server,
+}
+
+/// `timestamp` in `timezone`, for display (`2024-05-01 12:00:00 CEST`).
+pub fn format_time(timestamp: DateTime<Utc>, timezone: Tz) -> String {
+    timestamp.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+impl OutputFormat {
+    /// Formats that aren't text, so they're never printed to a terminal.
+    pub fn is_binary(self) -> bool {
+        matches!(self, OutputFormat::Protobuf | OutputFormat::Avro)
+    }
+
+    /// The format an `--output` file's extension implies, if any; a `.gz`
+    /// suffix is looked past, so `results.ndjson.gz` is still ndjson.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let path = match is_gzip(path) {
+            true => Path::new(path.file_stem()?),
+            false => path,
+        };
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(OutputFormat::Json),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "yaml" | "yml" => Some(OutputFormat::Yaml),
+            "csv" => Some(OutputFormat::Csv),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            "pb" | "protobuf" => Some(OutputFormat::Protobuf),
+            "env" => Some(OutputFormat::Env),
+            "html" | "htm" => Some(OutputFormat::Html),
+            "plist" => Some(OutputFormat::Plist),
+            "avro" => Some(OutputFormat::Avro),
+            _ => None,
+        }
+    }
+}
+
+/// Options controlling how results are rendered.
+pub struct OutputOptions {
+    pub summary_only: bool,
+    pub ping_only: bool,
+    pub unit: SpeedUnit,
+    pub csv_header: bool,
+    /// Result fields to emit as CSV columns, in order. `None` keeps every field.
+    pub csv_columns: Option<Vec<String>>,
+    pub csv_delimiter: u8,
+    pub csv_layout: CsvLayout,
+    /// Start CSV output with a `# unit:` line and name the unit in every speed column.
+    pub csv_units: bool,
+    /// Zone timestamps are displayed in; stored and machine-readable output stays UTC.
+    pub timezone: Tz,
+    /// Render JSON, YAML and text as a per-region matrix (`--regions`).
+    pub regions: bool,
+    /// Metrics `--format oneline` shows, in order.
+    pub oneline_fields: Vec<OnelineField>,
+}
+
+/// Short names accepted by `--csv-columns` in addition to the full field names.
+const COLUMN_ALIASES: [(&str, &str); 6] = [
+    ("download", "download_speed_mbps"),
+    ("upload", "upload_speed_mbps"),
+    ("ping", "ping_ms"),
+    ("jitter", "jitter_ms"),
+    ("server", "server_id"),
+    ("time", "timestamp"),
+];
+
+/// The result field a short alias such as `download` stands for, or `name` itself.
+pub fn field_name(name: &str) -> &str {
+    COLUMN_ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, field)| field)
+}
+
+/// Resolves `--csv-columns` names (or aliases) against the fields of `sample`.
+pub fn resolve_csv_columns(names: &[String], sample: &SpeedTestResult) -> Result<Vec<String>, String> {
+    let fields = serde_json::to_value(sample).unwrap();
+    names
+        .iter()
+        .map(|name| {
+            let field = field_name(name);
+            match fields.get(field) {
+                Some(_) => Ok(field.to_string()),
+                None => Err(format!("unknown CSV column '{}'", name)),
+            }
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    results: &'a [SpeedTestResult],
+    summaries: Vec<Summary>,
+}
+
+#[derive(Serialize)]
+struct SummaryRow<'a> {
+    server_id: &'a str,
+    metric: &'a str,
+    min: f64,
+    max: f64,
+    mean: f64,
+    median: f64,
+    stddev: f64,
+}
+
+/// Renders `results` as the bytes to write out, which for the binary
+/// formats aren't text.
+pub fn render_bytes(format: OutputFormat, results: &[SpeedTestResult], options: &OutputOptions) -> Vec<u8> {
+    match format {
+        OutputFormat::Protobuf => protobuf::encode(results),
+        OutputFormat::Avro => avro::encode(results),
+        _ => render(format, results, options).into_bytes(),
+    }
+}
+
+/// Renders the results of a run in the requested format.
+///
+/// A single run renders exactly as a lone result. Multiple runs render every
+/// result followed by one summary per server, unless `summary_only` is set.
+pub fn render(format: OutputFormat, results: &[SpeedTestResult], options: &OutputOptions) -> String {
+    let summaries = Summary::by_server(results);
+    let summary_only = options.summary_only;
+
+    match format {
+        OutputFormat::Json if options.regions && !summary_only => {
+            serde_json::to_string_pretty(&by_region(results)).unwrap()
+        }
+        OutputFormat::Yaml if options.regions && !summary_only => serde_yaml::to_string(&by_region(results)).unwrap(),
+        OutputFormat::Text if options.regions && !summary_only => text_region_matrix(results, options),
+        OutputFormat::Json if summary_only => serde_json::to_string_pretty(&summaries).unwrap(),
+        OutputFormat::Json if results.len() == 1 => serde_json::to_string_pretty(&results[0]).unwrap(),
+        OutputFormat::Json => serde_json::to_string_pretty(&Report { results, summaries }).unwrap(),
+        // NDJSON carries just the records, so each cycle appends cleanly to a stream.
+        OutputFormat::Ndjson if summary_only => json_lines(&summaries),
+        OutputFormat::Ndjson => json_lines(results),
+        OutputFormat::Yaml if summary_only => serde_yaml::to_string(&summaries).unwrap(),
+        OutputFormat::Yaml if results.len() == 1 => serde_yaml::to_string(&results[0]).unwrap(),
+        OutputFormat::Yaml => serde_yaml::to_string(&Report { results, summaries }).unwrap(),
+        // CSV stays a single table: the per-run rows, or the summary rows alone.
+        OutputFormat::Csv if summary_only => {
+            let mut wtr = csv_writer(options);
+            for summary in &summaries {
+                for (metric, stats) in summary.metrics() {
+                    wtr.serialize(SummaryRow {
+                        server_id: &summary.server_id,
+                        metric,
+                        min: stats.min,
+                        max: stats.max,
+                        mean: stats.mean,
+                        median: stats.median,
+                        stddev: stats.stddev,
+                    })
+                    .unwrap();
+                }
+            }
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap()
+        }
+        OutputFormat::Csv if options.csv_layout == CsvLayout::Wide => csv_wide(results, options),
+        OutputFormat::Csv => {
+            let mut wtr = csv_writer(options);
+            match &options.csv_columns {
+                Some(columns) => {
+                    if options.csv_header {
+                        wtr.write_record(columns.iter().map(|column| csv_header_name(column, options))).unwrap();
+                    }
+                    for result in results {
+                        wtr.write_record(project(result, columns)).unwrap();
+                    }
+                }
+                None => {
+                    // Nested fields such as the histogram don't fit in a CSV cell.
+                    let columns = results.first().map(scalar_fields).unwrap_or_default();
+                    if options.csv_header {
+                        wtr.write_record(columns.iter().map(|column| csv_header_name(column, options))).unwrap();
+                    }
+                    for result in results {
+                        wtr.write_record(project(result, &columns)).unwrap();
+                    }
+                }
+            }
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap()
+        }
+        OutputFormat::InfluxAnnotatedCsv => influx_annotated_csv(results),
+        OutputFormat::InfluxLine => results.iter().map(influx_line).collect::<Vec<_>>().join("\n"),
+        OutputFormat::Oneline => results.iter().map(|result| oneline(result, options)).collect::<Vec<_>>().join("\n"),
+        OutputFormat::Env => env_vars(&summaries, options),
+        OutputFormat::Html => html::report(results, &summaries, options),
+        OutputFormat::Plist if summary_only => plist_xml(&summaries),
+        OutputFormat::Plist if results.len() == 1 => plist_xml(&results[0]),
+        OutputFormat::Plist => plist_xml(&Report { results, summaries }),
+        OutputFormat::GrafanaJson => serde_json::to_string_pretty(&grafana_series(results)).unwrap(),
+        OutputFormat::Protobuf | OutputFormat::Avro => unreachable!("binary formats are rendered by render_bytes"),
+        OutputFormat::Markdown if summary_only => markdown_summaries(&summaries, options),
+        OutputFormat::Markdown if results.len() == 1 => markdown_results(results, options),
+        OutputFormat::Markdown => {
+            format!("{}\n{}", markdown_results(results, options), markdown_summaries(&summaries, options))
+        }
+        OutputFormat::Text if summary_only => text_summaries(&summaries, options),
+        OutputFormat::Text if results.len() == 1 => format!("Results:\n{}", text_metrics(&results[0], options)),
+        OutputFormat::Text => {
+            let mut output = String::new();
+            for summary in &summaries {
+                let runs = results.iter().filter(|r| r.server_id == summary.server_id);
+                for (i, result) in runs.enumerate() {
+                    let heading = match summaries.len() {
+                        1 => format!("Iteration {}", i + 1),
+                        _ => format!("{} - iteration {}", result.server_id, i + 1),
+                    };
+                    output.push_str(&format!("{}:\n{}\n\n", heading, text_metrics(result, options)));
+                }
+            }
+            output.push_str(&text_summaries(&summaries, options));
+            output
+        }
+    }
+}
+
+/// `↓235 ↑40 ⟳12ms`: the selected fields with speeds as whole numbers in
+/// the display unit. Ping-only runs leave out the speeds they didn't measure.
+fn oneline(result: &SpeedTestResult, options: &OutputOptions) -> String {
+    let unit = options.unit;
+    options
+        .oneline_fields
+        .iter()
+        .filter_map(|field| match field {
+            OnelineField::Download if !options.ping_only => {
+                Some(format!("↓{:.0}", unit.convert(result.download_speed_mbps as f64)))
+            }
+            OnelineField::Upload if !options.ping_only => {
+                Some(format!("↑{:.0}", unit.convert(result.upload_speed_mbps as f64)))
+            }
+            OnelineField::Ping => Some(format!("⟳{:.0}ms", result.ping_ms)),
+            OnelineField::Jitter if !options.ping_only => Some(format!("±{:.0}ms", result.jitter_ms)),
+            OnelineField::Server => Some(result.server_id.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `DOWNLOAD_MBPS=235.4` and friends, always in Mbps whatever `--unit` is so
+/// scripts can rely on the names. Several iterations report their mean; with
+/// several servers every name is prefixed with the server, e.g.
+/// `US_EAST_DOWNLOAD_MBPS`.
+fn env_vars(summaries: &[Summary], options: &OutputOptions) -> String {
+    let mut lines = Vec::new();
+    for summary in summaries {
+        let prefix = match summaries.len() {
+            1 => String::new(),
+            _ => format!("{}_", env_name(&summary.server_id)),
+        };
+        let mut vars = vec![("SERVER_ID", shell_quote(&summary.server_id))];
+        if !options.ping_only {
+            vars.push(("DOWNLOAD_MBPS", format!("{:.1}", summary.download_speed_mbps.mean)));
+            vars.push(("UPLOAD_MBPS", format!("{:.1}", summary.upload_speed_mbps.mean)));
+        }
+        vars.push(("PING_MS", format!("{:.0}", summary.ping_ms.mean)));
+        if !options.ping_only {
+            vars.push(("JITTER_MS", format!("{:.1}", summary.jitter_ms.mean)));
+        }
+        lines.extend(vars.into_iter().map(|(name, value)| format!("{}{}={}", prefix, name, value)));
+    }
+    lines.join("\n")
+}
+
+/// `server_id` as a variable name: uppercase, anything else becomes `_`.
+fn env_name(server_id: &str) -> String {
+    server_id.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect()
+}
+
+/// `value` as the shell reads it back, single-quoted unless it is plainly safe.
+fn shell_quote(value: &str) -> String {
+    match value.chars().all(|c| c.is_ascii_alphanumeric() || "_-.:/@".contains(c)) && !value.is_empty() {
+        true => value.to_string(),
+        false => format!("'{}'", value.replace('\'', r"'\''")),
+    }
+}
+
+/// `value` as an XML property list, with timestamps as `<date>` elements
+/// rather than the strings serde produces.
+fn plist_xml<T: Serialize>(value: &T) -> String {
+    let mut value = plist::to_value(value).unwrap();
+    plist_dates(&mut value);
+    let mut xml = Vec::new();
+    value.to_writer_xml(&mut xml).unwrap();
+    String::from_utf8(xml).unwrap()
+}
+
+fn plist_dates(value: &mut plist::Value) {
+    match value {
+        plist::Value::Dictionary(dict) => {
+            for (key, field) in dict.iter_mut() {
+                let date = match field {
+                    plist::Value::String(text) if key == "timestamp" => DateTime::parse_from_rfc3339(text).ok(),
+                    _ => None,
+                };
+                match date {
+                    Some(date) => *field = plist::Value::Date(std::time::SystemTime::from(date).into()),
+                    None => plist_dates(field),
+                }
+            }
+        }
+        plist::Value::Array(items) => items.iter_mut().for_each(plist_dates),
+        _ => {}
+    }
+}
+
+fn json_lines<T: Serialize>(records: &[T]) -> String {
+    records.iter().map(|record| serde_json::to_string(record).unwrap()).collect::<Vec<_>>().join("\n")
+}
+
+/// Results grouped by region (the server label), in the order regions were given.
+fn by_region(results: &[SpeedTestResult]) -> serde_json::Value {
+    let mut regions = serde_json::Map::new();
+    for result in results {
+        let runs = regions.entry(result.server_id.clone()).or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        runs.as_array_mut().unwrap().push(serde_json::to_value(result).unwrap());
+    }
+    serde_json::Value::Object(regions)
+}
+
+/// Reads results back from any shape `--format json` writes: a single
+/// result, a `{results, summaries}` report, a per-region map, or an array.
+pub fn parse_json(json: &str) -> Result<Vec<SpeedTestResult>, String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let runs = match value {
+        serde_json::Value::Object(mut report) if report.contains_key("results") => report.remove("results").unwrap(),
+        serde_json::Value::Object(ref map) if !map.contains_key("server_id") => serde_json::Value::Array(
+            map.values().flat_map(|runs| runs.as_array().cloned().unwrap_or_default()).collect(),
+        ),
+        serde_json::Value::Object(_) => serde_json::Value::Array(vec![value]),
+        runs => runs,
+    };
+    let summaries_only = runs.as_array().is_some_and(|runs| runs.iter().any(|run| run.get("iterations").is_some()));
+    if summaries_only || runs.as_array().is_some_and(Vec::is_empty) {
+        return Err("no results found (--summary-only output can't be exported)".to_string());
+    }
+    serde_json::from_value(runs).map_err(|e| e.to_string())
+}
+
+fn text_region_matrix(results: &[SpeedTestResult], options: &OutputOptions) -> String {
+    let unit = options.unit;
+    let mut output = format!(
+        "{:<16} {:>14} {:>14} {:>9} {:>11}",
+        "Region",
+        format!("Down {}", unit.label()),
+        format!("Up {}", unit.label()),
+        "Ping ms",
+        "Jitter ms"
+    );
+    for result in results {
+        output.push_str(&format!(
+            "\n{:<16} {:>14.2} {:>14.2} {:>9.0} {:>11.2}",
+            result.server_id,
+            unit.convert(result.download_speed_mbps as f64),
+            unit.convert(result.upload_speed_mbps as f64),
+            result.ping_ms,
+            result.jitter_ms
+        ));
+    }
+    output
+}
+
+/// Appends `line` and a newline to `path`, creating it if needed, for
+/// formats like NDJSON whose files grow record by record.
+pub fn append_line(path: &Path, line: &[u8], compress: bool) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let mut contents = line.to_vec();
+    contents.push(b'\n');
+    // Each append is its own gzip member; gzip readers decode concatenated members as one stream.
+    if compress {
+        contents = gzip(&contents);
+    }
+    file.write_all(&contents)?;
+    file.sync_data()
+}
+
+/// Whether `path` names a gzip file, which `--output` then compresses.
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// `contents` as a complete gzip stream.
+pub fn gzip(contents: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(contents).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it over
+/// the target, so readers never observe a truncated or partial file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "output path has no file name"))?;
+    let tmp = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+
+    let write = || {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    };
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Measurement name used for InfluxDB exports.
+const INFLUX_MEASUREMENT: &str = "internet_speed";
+
+/// The metrics InfluxDB exports carry as fields, in column order.
+fn influx_fields(result: &SpeedTestResult) -> [(&'static str, f32); 5] {
+    [
+        ("download_speed_mbps", result.download_speed_mbps),
+        ("upload_speed_mbps", result.upload_speed_mbps),
+        ("ping_ms", result.ping_ms),
+        ("jitter_ms", result.jitter_ms),
+        ("ttfb_ms", result.ttfb_ms),
+    ]
+}
+
+/// `result` as one line of InfluxDB line protocol, with the server as a tag
+/// and a nanosecond timestamp. Both the `--format influx-line` output and
+/// the InfluxDB exporter write this.
+pub fn influx_line(result: &SpeedTestResult) -> String {
+    // Tag values escape commas, spaces and equals signs with a backslash.
+    let server: String = result
+        .server_id
+        .chars()
+        .flat_map(|c| match c {
+            ',' | ' ' | '=' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect();
+    let fields: Vec<String> = influx_fields(result).iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    format!(
+        "{},server_id={} {} {}",
+        INFLUX_MEASUREMENT,
+        server,
+        fields.join(","),
+        result.timestamp.timestamp_nanos_opt().unwrap_or_default()
+    )
+}
+
+/// Renders results as InfluxDB annotated CSV: the server is a tag, the
+/// metrics are double fields and the timestamp is RFC3339.
+fn influx_annotated_csv(results: &[SpeedTestResult]) -> String {
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    let fields = influx_fields(&SpeedTestResult::default()).map(|(name, _)| name);
+    let annotation = |name: &str, first: [&str; 3], rest: &str| {
+        let mut row = vec![name.to_string()];
+        row.extend(first.map(String::from));
+        row.extend(fields.map(|_| rest.to_string()));
+        row
+    };
+
+    wtr.write_record(annotation("#group", ["false", "false", "true"], "false")).unwrap();
+    wtr.write_record(annotation("#datatype", ["measurement", "dateTime:RFC3339", "tag"], "double")).unwrap();
+    wtr.write_record(annotation("#default", ["", "", ""], "")).unwrap();
+    wtr.write_record(["", "m", "time", "server_id"].into_iter().chain(fields)).unwrap();
+
+    for result in results {
+        let mut row = vec![
+            String::new(),
+            INFLUX_MEASUREMENT.to_string(),
+            result.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            result.server_id.clone(),
+        ];
+        row.extend(influx_fields(result).map(|(_, value)| value.to_string()));
+        wtr.write_record(row).unwrap();
+    }
+    String::from_utf8(wtr.into_inner().unwrap()).unwrap()
+}
+
+/// One markdown table row per result, with the timestamp and server.
+fn markdown_results(results: &[SpeedTestResult], options: &OutputOptions) -> String {
+    let unit = options.unit.label();
+    let mut header = vec!["Time".to_string(), "Server".to_string()];
+    if !options.ping_only {
+        header.push(format!("Download ({})", unit));
+        header.push(format!("Upload ({})", unit));
+    }
+    header.push("Ping (ms)".to_string());
+    if !options.ping_only {
+        header.push("Jitter (ms)".to_string());
+    }
+
+    let rows = results.iter().map(|result| {
+        let mut row = vec![format_time(result.timestamp, options.timezone), result.server_id.clone()];
+        if !options.ping_only {
+            row.push(format!("{:.2}", options.unit.convert(result.download_speed_mbps as f64)));
+            row.push(format!("{:.2}", options.unit.convert(result.upload_speed_mbps as f64)));
+        }
+        row.push(format!("{:.0}", result.ping_ms));
+        if !options.ping_only {
+            row.push(format!("{:.2}", result.jitter_ms));
+        }
+        row
+    });
+    markdown_table(&header, rows)
+}
+
+/// One markdown table row per server and metric.
+fn markdown_summaries(summaries: &[Summary], options: &OutputOptions) -> String {
+    let header = ["Server", "Metric", "Min", "Max", "Mean", "Median", "Stddev"].map(String::from);
+    let rows = summaries.iter().flat_map(|summary| {
+        summary.metrics().into_iter().filter(|(metric, _)| !options.ping_only || *metric == "ping_ms").map(
+            |(metric, stats)| {
+                let is_speed = metric.ends_with("_mbps");
+                let convert = |value: f64| if is_speed { options.unit.convert(value) } else { value };
+                let mut row = vec![summary.server_id.clone(), metric.to_string()];
+                row.extend(
+                    [stats.min, stats.max, stats.mean, stats.median, stats.stddev]
+                        .map(|v| format!("{:.2}", convert(v))),
+                );
+                row
+            },
+        )
+    });
+    markdown_table(&header, rows)
+}
+
+fn markdown_table(header: &[String], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let line = |cells: &[String]| format!("| {} |\n", cells.join(" | "));
+    let mut table = line(header);
+    table.push_str(&line(&header.iter().map(|_| "---".to_string()).collect::<Vec<_>>()));
+    for row in rows {
+        table.push_str(&line(&row));
+    }
+    table
+}
+
+#[derive(Serialize)]
+struct GrafanaSeries {
+    target: String,
+    /// `[value, unix milliseconds]` pairs, oldest first.
+    datapoints: Vec<(f32, i64)>,
+}
+
+/// One series per metric (and per server when several were tested), in the
+/// shape the Grafana JSON datasource expects from a query.
+fn grafana_series(results: &[SpeedTestResult]) -> Vec<GrafanaSeries> {
+    type Metric = (&'static str, fn(&SpeedTestResult) -> f32);
+    let metrics: [Metric; 5] = [
+        ("download_speed_mbps", |r| r.download_speed_mbps),
+        ("upload_speed_mbps", |r| r.upload_speed_mbps),
+        ("ping_ms", |r| r.ping_ms),
+        ("jitter_ms", |r| r.jitter_ms),
+        ("ttfb_ms", |r| r.ttfb_ms),
+    ];
+    let summaries = Summary::by_server(results);
+    let mut series = Vec::new();
+    for summary in &summaries {
+        let mut runs: Vec<&SpeedTestResult> = results.iter().filter(|r| r.server_id == summary.server_id).collect();
+        runs.sort_by_key(|r| r.timestamp);
+        for (metric, value) in metrics {
+            series.push(GrafanaSeries {
+                target: match summaries.len() {
+                    1 => metric.to_string(),
+                    _ => format!("{}.{}", summary.server_id, metric),
+                },
+                datapoints: runs.iter().map(|r| (value(r), r.timestamp.timestamp_millis())).collect(),
+            });
+        }
+    }
+    series
+}
+
+fn csv_writer(options: &OutputOptions) -> csv::Writer<Vec<u8>> {
+    let mut out = Vec::new();
+    // Only with the header, so rows appended with --csv-no-header stay plain.
+    if options.csv_units && options.csv_header {
+        out.extend(format!("# unit: {}\n", options.unit.label()).into_bytes());
+    }
+    csv::WriterBuilder::new().has_headers(options.csv_header).delimiter(options.csv_delimiter).from_writer(out)
+}
+
+/// `column` as the CSV header shows it: with `--csv-units-in-header` the
+/// speeds converted to `--unit`, whose names carry no unit, get its suffix.
+fn csv_header_name(column: &str, options: &OutputOptions) -> String {
+    match column {
+        "download_speed" | "upload_speed" if options.csv_units => format!("{}_{}", column, options.unit.suffix()),
+        _ => column.to_string(),
+    }
+}
+
+/// Metrics `--csv-layout wide` spreads across iterations unless `--csv-columns` picks others.
+const WIDE_METRICS: [&str; 4] = ["download_speed_mbps", "upload_speed_mbps", "ping_ms", "jitter_ms"];
+
+/// The results pivoted to one row per server (in the order servers were
+/// first tested) with `iter<N>_<metric>` columns, metrics named by their
+/// short alias where there is one. Servers with fewer iterations leave the
+/// missing cells empty.
+fn csv_wide(results: &[SpeedTestResult], options: &OutputOptions) -> String {
+    let metrics: Vec<String> = match &options.csv_columns {
+        Some(columns) => columns.iter().filter(|column| *column != "server_id").cloned().collect(),
+        None => WIDE_METRICS.iter().map(|metric| metric.to_string()).collect(),
+    };
+    let mut servers: Vec<(&str, Vec<&SpeedTestResult>)> = Vec::new();
+    for result in results {
+        match servers.iter_mut().find(|(id, _)| *id == result.server_id) {
+            Some((_, runs)) => runs.push(result),
+            None => servers.push((&result.server_id, vec![result])),
+        }
+    }
+    let iterations = servers.iter().map(|(_, runs)| runs.len()).max().unwrap_or(0);
+
+    let mut wtr = csv_writer(options);
+    if options.csv_header {
+        let mut header = vec!["server_id".to_string()];
+        for iteration in 1..=iterations {
+            for metric in &metrics {
+                let alias = COLUMN_ALIASES.iter().find(|(_, field)| field == metric);
+                let name = alias.map_or_else(|| csv_header_name(metric, options), |(alias, _)| alias.to_string());
+                header.push(format!("iter{}_{}", iteration, name));
+            }
+        }
+        wtr.write_record(&header).unwrap();
+    }
+    for (server_id, runs) in &servers {
+        let mut row = vec![server_id.to_string()];
+        for iteration in 0..iterations {
+            match runs.get(iteration) {
+                Some(result) => row.extend(project(result, &metrics)),
+                None => row.extend(metrics.iter().map(|_| String::new())),
+            }
+        }
+        wtr.write_record(&row).unwrap();
+    }
+    String::from_utf8(wtr.into_inner().unwrap()).unwrap()
+}
+
+/// Names of the fields of `result` that render as a single CSV cell.
+fn scalar_fields(result: &SpeedTestResult) -> Vec<String> {
+    match serde_json::to_value(result).unwrap() {
+        serde_json::Value::Object(fields) => fields
+            .into_iter()
+            .filter(|(_, value)| !value.is_array() && !value.is_object())
+            .map(|(name, _)| name)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Picks the given fields out of a result, rendered as CSV cells.
+fn project(result: &SpeedTestResult, columns: &[String]) -> Vec<String> {
+    let fields = serde_json::to_value(result).unwrap();
+    columns
+        .iter()
+        .map(|column| match fields.get(column) {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        })
+        .collect()
+}
+
+fn text_summaries(summaries: &[Summary], options: &OutputOptions) -> String {
+    let show_server = summaries.len() > 1;
+    summaries.iter().map(|summary| text_summary(summary, options, show_server)).collect::<Vec<_>>().join("\n\n")
+}
+
+fn text_metrics(result: &SpeedTestResult, options: &OutputOptions) -> String {
+    let mut time = format_time(result.timestamp, options.timezone);
+    if result.cached {
+        time.push_str(" (cached)");
+    }
+    let cold = if result.cold_connections { " (cold connections)" } else { "" };
+    let phases = &result.phases;
+    let ping = or_failed(&phases.latency, || format!("{:.0}ms{}", result.ping_ms, cold));
+    let waterfall = result.connection_profile.as_ref().map(connection_waterfall).unwrap_or_default();
+    if options.ping_only {
+        return format!("Time: {}\nPing: {}{}", time, ping, waterfall);
+    }
+    let unit = options.unit;
+    let download = or_failed(&phases.download, || {
+        format!(
+            "{:.2} {}{}",
+            unit.convert(result.download_speed_mbps as f64),
+            unit.label(),
+            match (result.download_checksum_valid, result.download_truncated, result.unreliable) {
+                (Some(false), _, _) => " (checksum mismatch, content altered in transit)",
+                (_, true, _) => " (truncated transfer, unreliable)",
+                (_, false, true) => " (too short to be reliable)",
+                _ if result.download_cache_suspected == Some(true) => " (probably served from a cache)",
+                _ => "",
+            }
+        )
+    });
+    let upload =
+        or_failed(&phases.upload, || format!("{:.2} {}", unit.convert(result.upload_speed_mbps as f64), unit.label()));
+    let jitter = or_failed(&phases.jitter, || format!("{:.2}ms", result.jitter_ms));
+    let mut output =
+        format!("Time: {}\nDownload: {}\nUpload: {}\nPing: {}\nJitter: {}", time, download, upload, ping, jitter);
+    if let (Some(burst), Some(sustained)) = (result.download_burst_mbps, result.download_sustained_mbps) {
+        output.push_str(&format!(
+            "\nDownload burst (first 2s): {:.2} {}, sustained: {:.2} {}",
+            unit.convert(burst as f64),
+            unit.label(),
+            unit.convert(sustained as f64),
+            unit.label()
+        ));
+    }
+    let loaded = |ping: Option<f32>| ping.map_or("-".to_string(), |ping| format!("{:.0}ms", ping));
+    if result.loaded_ping_ms.is_some() || result.upload_loaded_ping_ms.is_some() {
+        output.push_str(&format!(
+            "\nLoaded ping: {} during download, {} during upload",
+            loaded(result.loaded_ping_ms),
+            loaded(result.upload_loaded_ping_ms)
+        ));
+    }
+    let outliers = result.latency_outliers.unwrap_or(0) + result.jitter_outliers.unwrap_or(0);
+    if outliers > 0 {
+        output.push_str(&format!(
+            "\nOutliers excluded: {} ping, {} jitter sample(s)",
+            result.latency_outliers.unwrap_or(0),
+            result.jitter_outliers.unwrap_or(0)
+        ));
+    }
+    if let Some(steady) = result.download_steady_mbps {
+        output.push_str(&format!("\nDownload steady state: {:.2} {}", unit.convert(steady as f64), unit.label()));
+    }
+    if let Some(capacity) = result.capacity_mbps {
+        output.push_str(&format!(
+            "\nCapacity (best of {} probes): {:.2} {}",
+            result.capacity_probes_mbps.len(),
+            unit.convert(capacity as f64),
+            unit.label()
+        ));
+    }
+    if let Some(efficiency) = result.efficiency_percent {
+        output.push_str(&format!("\nEfficiency: achieved {:.0}% of line rate", efficiency));
+    }
+    if let Some(isp) = &result.isp {
+        match result.asn {
+            Some(asn) => output.push_str(&format!("\nISP: {} (AS{})", isp, asn)),
+            None => output.push_str(&format!("\nISP: {}", isp)),
+        }
+    }
+    if let Some(interface) = &result.egress_interface {
+        output.push_str(&format!("\nInterface: {}", interface));
+    }
+    output.push_str(&waterfall);
+    output
+}
+
+/// Width in characters of the bars of the --connection-profile waterfall.
+const WATERFALL_WIDTH: usize = 40;
+
+/// The connection profile as a waterfall, one line per step, each bar
+/// starting where the previous one ended and all of them spanning the
+/// time to first byte.
+fn connection_waterfall(profile: &ConnectionProfile) -> String {
+    let steps = [
+        ("DNS lookup", Some(profile.dns_ms)),
+        ("TCP connect", Some(profile.tcp_connect_ms)),
+        ("TLS handshake", profile.tls_ms),
+        ("First byte", Some(profile.ttfb_ms)),
+    ];
+    let scale = WATERFALL_WIDTH as f32 / profile.total_ms.max(f32::EPSILON);
+    let mut output = format!("\nConnection setup: {:.1} ms to first byte", profile.total_ms);
+    let mut elapsed = 0.0;
+    for (label, ms) in steps {
+        let Some(ms) = ms else { continue };
+        // Every step gets at least one cell, so a sub-millisecond lookup stays visible.
+        let start = ((elapsed * scale).round() as usize).min(WATERFALL_WIDTH - 1);
+        elapsed += ms;
+        let end = ((elapsed * scale).round() as usize).clamp(start + 1, WATERFALL_WIDTH);
+        output.push_str(&format!("\n  {:<14}{:>8.1} ms  {}{}", label, ms, " ".repeat(start), "█".repeat(end - start)));
+    }
+    output
+}
+
+/// `FAILED: <reason>` for a phase that failed, otherwise the rendered value.
+fn or_failed(phase: &Option<PhaseResult>, value: impl FnOnce() -> String) -> String {
+    match phase.as_ref().and_then(|phase| phase.error.as_ref()) {
+        Some(error) => format!("FAILED: {}", error),
+        None => value(),
+    }
+}
+
+fn text_summary(summary: &Summary, options: &OutputOptions, show_server: bool) -> String {
+    let mut output = match show_server {
+        true => format!("Summary for {} ({} iterations):", summary.server_id, summary.iterations),
+        false => format!("Summary ({} iterations):", summary.iterations),
+    };
+    let rows = [
+        ("Download", &summary.download_speed_mbps, true),
+        ("Upload", &summary.upload_speed_mbps, true),
+        ("Ping", &summary.ping_ms, false),
+        ("Jitter", &summary.jitter_ms, false),
+    ];
+    for (label, stats, is_speed) in rows {
+        if options.ping_only && label != "Ping" {
+            continue;
+        }
+        let convert = |value: f64| if is_speed { options.unit.convert(value) } else { value };
+        let unit = if is_speed { format!(" {}", options.unit.label()) } else { "ms".to_string() };
+        output.push_str(&format!(
+            "\n{}: min {:.2} / max {:.2} / mean {:.2} / median {:.2} / stddev {:.2}{}",
+            label,
+            convert(stats.min),
+            convert(stats.max),
+            convert(stats.mean),
+            convert(stats.median),
+            convert(stats.stddev),
+            unit
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phase::Phases;
+    use std::time::Duration;
+
+    fn options(csv_header: bool, csv_columns: Option<Vec<String>>) -> OutputOptions {
+        OutputOptions {
+            summary_only: false,
+            ping_only: false,
+            unit: SpeedUnit::Mbps,
+            csv_header,
+            csv_columns,
+            csv_delimiter: b',',
+            csv_layout: CsvLayout::Long,
+            csv_units: false,
+            timezone: Tz::UTC,
+            regions: false,
+            oneline_fields: vec![OnelineField::Download, OnelineField::Upload, OnelineField::Ping],
+        }
+    }
+
+    fn result() -> SpeedTestResult {
+        SpeedTestResult {
+            download_speed_mbps: 100.0,
+            upload_speed_mbps: 20.5,
+            ping_ms: 12.0,
+            server_id: "cloudflare".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn format_is_inferred_from_known_extensions() {
+        let format = |path: &str| OutputFormat::from_extension(Path::new(path));
+        assert!(format("results.csv") == Some(OutputFormat::Csv));
+        assert!(format("/var/lib/speed/latest.YML") == Some(OutputFormat::Yaml));
+        assert!(format("report.md") == Some(OutputFormat::Markdown));
+        assert!(format("results.xml").is_none());
+        assert!(format("results").is_none());
+    }
+
+    #[test]
+    fn csv_columns_select_and_order_fields() {
+        let names = ["ping", "download", "server_id"].map(String::from);
+        let columns = resolve_csv_columns(&names, &result()).unwrap();
+        assert_eq!(columns, ["ping_ms", "download_speed_mbps", "server_id"]);
+
+        let csv = render(OutputFormat::Csv, &[result()], &options(true, Some(columns)));
+        assert_eq!(csv, "ping_ms,download_speed_mbps,server_id\n12.0,100.0,cloudflare\n");
+    }
+
+    #[test]
+    fn csv_units_in_header_name_the_display_unit() {
+        let columns = ["download_speed_mbps", "download_speed", "ping_ms"].map(String::from).to_vec();
+        let converted = SpeedTestResult { download_speed: Some(12.5), ..result() };
+        let options = OutputOptions { unit: SpeedUnit::Mbyteps, csv_units: true, ..options(true, Some(columns)) };
+
+        let csv = render(OutputFormat::Csv, std::slice::from_ref(&converted), &options);
+        assert_eq!(csv, "# unit: MB/s\ndownload_speed_mbps,download_speed_mbyteps,ping_ms\n100.0,12.5,12.0\n");
+        let rows = render(OutputFormat::Csv, &[converted], &OutputOptions { csv_header: false, ..options });
+        assert_eq!(rows, "100.0,12.5,12.0\n");
+    }
+
+    #[test]
+    fn csv_header_can_be_suppressed() {
+        let columns = Some(vec!["upload_speed_mbps".to_string()]);
+        assert_eq!(render(OutputFormat::Csv, &[result()], &options(false, columns)), "20.5\n");
+
+        let full = render(OutputFormat::Csv, &[result()], &options(false, None));
+        assert!(!full.contains("download_speed_mbps"));
+        assert_eq!(full.lines().count(), 1);
+    }
+
+    #[test]
+    fn csv_delimiter_is_configurable() {
+        let columns = Some(["ping_ms", "download_speed_mbps"].map(String::from).to_vec());
+        let options = OutputOptions { csv_delimiter: b';', ..options(true, columns) };
+        assert_eq!(render(OutputFormat::Csv, &[result()], &options), "ping_ms;download_speed_mbps\n12.0;100.0\n");
+    }
+
+    #[test]
+    fn influx_annotated_csv_has_annotations_and_rows() {
+        let csv = render(OutputFormat::InfluxAnnotatedCsv, &[result()], &options(true, None));
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], "#group,false,false,true,false,false,false,false,false");
+        assert_eq!(lines[1], "#datatype,measurement,dateTime:RFC3339,tag,double,double,double,double,double");
+        assert_eq!(lines[3], ",m,time,server_id,download_speed_mbps,upload_speed_mbps,ping_ms,jitter_ms,ttfb_ms");
+        assert_eq!(lines[4], ",internet_speed,1970-01-01T00:00:00.000Z,cloudflare,100,20.5,12,0,0");
+    }
+
+    #[test]
+    fn influx_line_protocol_escapes_the_server_tag() {
+        let timestamp = DateTime::from_timestamp(1, 5).unwrap();
+        let result = SpeedTestResult { server_id: "lab, rack=2".to_string(), timestamp, ..result() };
+        let lines = render(OutputFormat::InfluxLine, &[result], &options(true, None));
+        assert_eq!(
+            lines,
+            "internet_speed,server_id=lab\\,\\ rack\\=2 \
+             download_speed_mbps=100,upload_speed_mbps=20.5,ping_ms=12,jitter_ms=0,ttfb_ms=0 1000000005"
+        );
+    }
+
+    #[test]
+    fn markdown_renders_a_table_per_result() {
+        let table = render(OutputFormat::Markdown, &[result()], &options(true, None));
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines[0], "| Time | Server | Download (Mbps) | Upload (Mbps) | Ping (ms) | Jitter (ms) |");
+        assert_eq!(lines[1], "| --- | --- | --- | --- | --- | --- |");
+        assert_eq!(lines[2], "| 1970-01-01 00:00:00 UTC | cloudflare | 100.00 | 20.50 | 12 | 0.00 |");
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn timestamps_are_displayed_in_the_chosen_zone() {
+        let result = SpeedTestResult { timestamp: "2024-05-01T10:00:00Z".parse().unwrap(), ..result() };
+        let options = OutputOptions { timezone: Tz::Europe__Berlin, ..options(true, None) };
+
+        let text = render(OutputFormat::Text, std::slice::from_ref(&result), &options);
+        assert!(text.starts_with("Results:\nTime: 2024-05-01 12:00:00 CEST\n"), "{}", text);
+        // Machine-readable output keeps UTC.
+        let json = render(OutputFormat::Json, &[result], &options);
+        assert!(json.contains("2024-05-01T10:00:00Z"));
+    }
+
+    #[test]
+    fn cold_connection_ping_is_labelled() {
+        let cold = SpeedTestResult { cold_connections: true, ..result() };
+        let text = render(OutputFormat::Text, &[cold], &options(true, None));
+        assert!(text.contains("\nPing: 12ms (cold connections)\n"), "{}", text);
+        assert!(!render(OutputFormat::Text, &[result()], &options(true, None)).contains("cold"));
+    }
+
+    #[test]
+    fn grafana_json_has_a_series_per_metric() {
+        let json = render(OutputFormat::GrafanaJson, &[result()], &options(true, None));
+        let series: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(series.as_array().unwrap().len(), 5);
+        assert_eq!(series[0]["target"], "download_speed_mbps");
+        assert_eq!(series[0]["datapoints"], serde_json::json!([[100.0, 0]]));
+        assert_eq!(series[2]["target"], "ping_ms");
+    }
+
+    #[test]
+    fn regions_render_keyed_by_region() {
+        let eu = SpeedTestResult { server_id: "eu".to_string(), ..result() };
+        let us = SpeedTestResult { server_id: "us".to_string(), ..result() };
+        let options = OutputOptions { regions: true, ..options(true, None) };
+
+        let json: serde_json::Value = serde_json::from_str(&render(OutputFormat::Json, &[us, eu], &options)).unwrap();
+        let regions: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(regions, ["us", "eu"]);
+        assert_eq!(json["eu"][0]["download_speed_mbps"], 100.0);
+    }
+
+    #[test]
+    fn text_reports_line_rate_efficiency_when_known() {
+        let text = render(OutputFormat::Text, &[result()], &options(true, None));
+        assert!(!text.contains("Efficiency"));
+
+        let measured = SpeedTestResult { efficiency_percent: Some(94.2), ..result() };
+        let text = render(OutputFormat::Text, &[measured], &options(true, None));
+        assert!(text.ends_with("Efficiency: achieved 94% of line rate"));
+    }
+
+    #[test]
+    fn parse_json_reads_back_every_rendered_shape() {
+        let single = render(OutputFormat::Json, &[result()], &options(true, None));
+        let report = render(OutputFormat::Json, &[result(), result()], &options(true, None));
+        let regions =
+            render(OutputFormat::Json, &[result(), result()], &OutputOptions { regions: true, ..options(true, None) });
+
+        assert_eq!(parse_json(&single).unwrap()[0].download_speed_mbps, 100.0);
+        assert_eq!(parse_json(&report).unwrap().len(), 2);
+        assert_eq!(parse_json(&regions).unwrap().len(), 2);
+        assert_eq!(parse_json(&format!("[{}]", single)).unwrap()[0].server_id, "cloudflare");
+
+        let summaries =
+            render(OutputFormat::Json, &[result()], &OutputOptions { summary_only: true, ..options(true, None) });
+        assert!(parse_json(&summaries).is_err());
+    }
+
+    #[test]
+    fn ndjson_puts_each_result_on_its_own_line() {
+        let us = SpeedTestResult { server_id: "us".to_string(), ..result() };
+        let ndjson = render(OutputFormat::Ndjson, &[result(), us], &options(true, None));
+
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let second: SpeedTestResult = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(second.server_id, "us");
+    }
+
+    #[test]
+    fn plist_output_uses_date_elements() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let plist = render(OutputFormat::Plist, &[SpeedTestResult { timestamp, ..result() }], &options(true, None));
+        assert!(plist.starts_with("<?xml"), "{}", plist);
+        assert!(plist.contains("<key>timestamp</key>\n\t<date>2024-05-01T12:00:00Z</date>"), "{}", plist);
+        assert!(plist.contains("<key>server_id</key>\n\t<string>cloudflare</string>"));
+
+        let parsed = plist::Value::from_reader_xml(plist.as_bytes()).unwrap();
+        assert_eq!(parsed.as_dictionary().unwrap()["download_speed_mbps"].as_real(), Some(100.0));
+        let several = render(OutputFormat::Plist, &[result(), result()], &options(true, None));
+        let several = plist::Value::from_reader_xml(several.as_bytes()).unwrap();
+        assert!(several.as_dictionary().unwrap().contains_key("summaries"));
+    }
+
+    #[test]
+    fn env_output_can_be_sourced_by_a_shell() {
+        let env = render(OutputFormat::Env, &[result()], &options(true, None));
+        assert_eq!(env, "SERVER_ID=cloudflare\nDOWNLOAD_MBPS=100.0\nUPLOAD_MBPS=20.5\nPING_MS=12\nJITTER_MS=0.0");
+
+        let eu = SpeedTestResult { server_id: "eu west's".to_string(), ..result() };
+        let env = render(OutputFormat::Env, &[result(), eu], &OutputOptions { ping_only: true, ..options(true, None) });
+        assert_eq!(
+            env,
+            "CLOUDFLARE_SERVER_ID=cloudflare\nCLOUDFLARE_PING_MS=12\nEU_WEST_S_SERVER_ID='eu west'\\''s'\nEU_WEST_S_PING_MS=12"
+        );
+    }
+
+    #[test]
+    fn wide_csv_has_a_row_per_server_and_columns_per_iteration() {
+        let eu = |download_speed_mbps| SpeedTestResult { server_id: "eu".to_string(), download_speed_mbps, ..result() };
+        let results = [result(), eu(50.0), SpeedTestResult { download_speed_mbps: 110.0, ..result() }];
+        let columns = Some(vec!["download_speed_mbps".to_string(), "ping_ms".to_string()]);
+        let options = OutputOptions { csv_layout: CsvLayout::Wide, ..options(true, columns) };
+
+        assert_eq!(
+            render(OutputFormat::Csv, &results, &options),
+            "server_id,iter1_download,iter1_ping,iter2_download,iter2_ping\n\
+             cloudflare,100.0,12.0,110.0,12.0\n\
+             eu,50.0,12.0,,\n"
+        );
+    }
+
+    #[test]
+    fn text_names_the_reason_a_phase_failed() {
+        let failed = PhaseResult::new(Err("connection failed: refused".to_string()), Duration::from_millis(5));
+        let phases = Phases { upload: Some(failed), ..Default::default() };
+        let text = render(OutputFormat::Text, &[SpeedTestResult { phases, ..result() }], &options(true, None));
+        assert!(
+            text.contains("\nDownload: 100.00 Mbps\nUpload: FAILED: connection failed: refused\nPing: 12ms"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn connection_profile_renders_as_a_waterfall() {
+        let connection_profile = Some(ConnectionProfile {
+            dns_ms: 10.0,
+            tcp_connect_ms: 10.0,
+            tls_ms: Some(20.0),
+            ttfb_ms: 40.0,
+            total_ms: 80.0,
+        });
+        let result = SpeedTestResult { connection_profile, ..result() };
+        let options = OutputOptions { ping_only: true, ..options(true, None) };
+
+        assert_eq!(
+            render(OutputFormat::Text, &[result], &options)
+                .lines()
+                .skip_while(|line| !line.starts_with("Connection setup"))
+                .collect::<Vec<_>>(),
+            [
+                "Connection setup: 80.0 ms to first byte",
+                "  DNS lookup        10.0 ms  █████",
+                "  TCP connect       10.0 ms       █████",
+                "  TLS handshake     20.0 ms            ██████████",
+                "  First byte        40.0 ms                      ████████████████████",
+            ]
+        );
+    }
+
+    #[test]
+    fn oneline_shows_the_selected_fields_tersely() {
+        assert_eq!(render(OutputFormat::Oneline, &[result()], &options(true, None)), "↓100 ↑20 ⟳12ms");
+
+        let fields = vec![OnelineField::Server, OnelineField::Ping, OnelineField::Download];
+        let options = OutputOptions { ping_only: true, oneline_fields: fields, ..options(true, None) };
+        assert_eq!(render(OutputFormat::Oneline, &[result()], &options), "cloudflare ⟳12ms");
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_target_without_leftovers() {
+        let dir = std::env::temp_dir().join(format!("speedtest-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("result.json");
+        fs::write(&path, "old contents that are longer").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compressed_appends_decode_as_one_stream() {
+        let path = std::env::temp_dir().join(format!("speedtest-output-{}.ndjson.gz", std::process::id()));
+        append_line(&path, b"{\"run\":1}", true).unwrap();
+        append_line(&path, b"{\"run\":2}", true).unwrap();
+
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(
+            &mut flate2::read::MultiGzDecoder::new(fs::File::open(&path).unwrap()),
+            &mut decoded,
+        )
+        .unwrap();
+        assert_eq!(decoded, "{\"run\":1}\n{\"run\":2}\n");
+        assert!(OutputFormat::from_extension(&path) == Some(OutputFormat::Ndjson));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unknown_csv_column_is_rejected() {
+        let names = ["bandwidth".to_string()];
+        assert!(resolve_csv_columns(&names, &result()).is_err());
+    }
+
+    #[test]
+    fn converts_mbps_to_other_units() {
+        assert_eq!(SpeedUnit::Mbps.convert(100.0), 100.0);
+        assert_eq!(SpeedUnit::Mbyteps.convert(100.0), 12.5);
+        assert_eq!(SpeedUnit::Gbps.convert(2500.0), 2.5);
+        assert!((SpeedUnit::Mibps.convert(100.0) - 95.367).abs() < 0.001);
+    }
+}
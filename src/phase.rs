@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How one test phase went: the value it measured, how long it ran and, if
+/// it couldn't measure anything, why. The headline fields of a result keep
+/// reporting a failed phase as 0 for compatibility; this says which zeros
+/// are failures.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PhaseResult {
+    pub value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: f64,
+}
+
+impl PhaseResult {
+    pub fn new(outcome: Result<f64, String>, duration: Duration) -> Self {
+        let (value, error) = match outcome {
+            Ok(value) => (value, None),
+            Err(error) => (0.0, Some(error)),
+        };
+        PhaseResult { value, error, duration_ms: duration.as_secs_f64() * 1000.0 }
+    }
+}
+
+/// The phases a run went through; phases that were skipped are absent.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Phases {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<PhaseResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload: Option<PhaseResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<PhaseResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<PhaseResult>,
+}
+
+impl Phases {
+    pub fn is_empty(&self) -> bool {
+        *self == Phases::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_phases_keep_their_reason_and_report_zero() {
+        let download = PhaseResult::new(Ok(250.0), Duration::from_secs(2));
+        let upload = PhaseResult::new(Err("connection failed".to_string()), Duration::from_millis(30));
+
+        assert_eq!((download.value, download.error, download.duration_ms), (250.0, None, 2000.0));
+        assert_eq!((upload.value, upload.error.as_deref()), (0.0, Some("connection failed")));
+        assert!(Phases::default().is_empty());
+        assert!(!Phases { upload: Some(upload), ..Default::default() }.is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::SpeedTestResult;
+use prost::Message;
+
+/// `SpeedTestResult` from `proto/speedtest.proto`, field for field. The
+/// messages are written out rather than generated by prost-build so building
+/// doesn't need `protoc`; keep them in sync with the schema.
+#[derive(Clone, PartialEq, Message)]
+pub struct ResultMessage {
+    #[prost(int64, tag = "1")]
+    pub timestamp_unix_ms: i64,
+    #[prost(string, tag = "2")]
+    pub run_id: String,
+    #[prost(float, tag = "3")]
+    pub download_speed_mbps: f32,
+    #[prost(float, tag = "4")]
+    pub upload_speed_mbps: f32,
+    #[prost(float, tag = "5")]
+    pub ping_ms: f32,
+    #[prost(string, tag = "6")]
+    pub server_id: String,
+    #[prost(float, tag = "7")]
+    pub jitter_ms: f32,
+    #[prost(float, tag = "8")]
+    pub ttfb_ms: f32,
+    #[prost(float, optional, tag = "9")]
+    pub packet_loss_percent: Option<f32>,
+    #[prost(float, optional, tag = "10")]
+    pub loaded_ping_ms: Option<f32>,
+    #[prost(uint32, tag = "11")]
+    pub download_streams: u32,
+    #[prost(bool, tag = "12")]
+    pub download_truncated: bool,
+    #[prost(uint32, tag = "13")]
+    pub retries: u32,
+    #[prost(string, tag = "14")]
+    pub http_version: String,
+    #[prost(message, repeated, tag = "15")]
+    pub download_histogram: Vec<HistogramBucketMessage>,
+    #[prost(float, optional, tag = "16")]
+    pub efficiency_percent: Option<f32>,
+    #[prost(bool, tag = "17")]
+    pub unreliable: bool,
+    #[prost(bool, optional, tag = "18")]
+    pub download_checksum_valid: Option<bool>,
+    #[prost(string, optional, tag = "19")]
+    pub signature: Option<String>,
+    #[prost(bool, tag = "20")]
+    pub cold_connections: bool,
+    #[prost(bool, tag = "21")]
+    pub cached: bool,
+    #[prost(float, optional, tag = "22")]
+    pub download_burst_mbps: Option<f32>,
+    #[prost(float, optional, tag = "23")]
+    pub download_sustained_mbps: Option<f32>,
+    #[prost(bool, optional, tag = "24")]
+    pub download_cache_suspected: Option<bool>,
+    #[prost(float, optional, tag = "25")]
+    pub download_steady_mbps: Option<f32>,
+    #[prost(string, optional, tag = "26")]
+    pub egress_interface: Option<String>,
+    #[prost(string, optional, tag = "27")]
+    pub final_url: Option<String>,
+    #[prost(uint32, optional, tag = "28")]
+    pub asn: Option<u32>,
+    #[prost(string, optional, tag = "29")]
+    pub isp: Option<String>,
+    #[prost(float, optional, tag = "30")]
+    pub upload_loaded_ping_ms: Option<f32>,
+    #[prost(string, repeated, tag = "31")]
+    pub jitter_hosts: Vec<String>,
+    #[prost(uint32, optional, tag = "32")]
+    pub download_size_mb: Option<u32>,
+    #[prost(bool, optional, tag = "33")]
+    pub download_within_target: Option<bool>,
+    #[prost(uint32, optional, tag = "34")]
+    pub latency_outliers: Option<u32>,
+    #[prost(uint32, optional, tag = "35")]
+    pub jitter_outliers: Option<u32>,
+    #[prost(float, optional, tag = "36")]
+    pub capacity_mbps: Option<f32>,
+    #[prost(float, repeated, tag = "37")]
+    pub capacity_probes_mbps: Vec<f32>,
+    #[prost(string, optional, tag = "38")]
+    pub upload_mode: Option<String>,
+    #[prost(message, optional, tag = "39")]
+    pub connection_profile: Option<ConnectionProfileMessage>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct HistogramBucketMessage {
+    #[prost(uint64, tag = "1")]
+    pub bucket_ms: u64,
+    #[prost(double, tag = "2")]
+    pub mbps: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConnectionProfileMessage {
+    #[prost(float, tag = "1")]
+    pub dns_ms: f32,
+    #[prost(float, tag = "2")]
+    pub tcp_connect_ms: f32,
+    #[prost(float, optional, tag = "3")]
+    pub tls_ms: Option<f32>,
+    #[prost(float, tag = "4")]
+    pub ttfb_ms: f32,
+    #[prost(float, tag = "5")]
+    pub total_ms: f32,
+}
+
+impl From<&SpeedTestResult> for ResultMessage {
+    fn from(result: &SpeedTestResult) -> Self {
+        ResultMessage {
+            timestamp_unix_ms: result.timestamp.timestamp_millis(),
+            run_id: result.run_id.to_string(),
+            download_speed_mbps: result.download_speed_mbps,
+            upload_speed_mbps: result.upload_speed_mbps,
+            ping_ms: result.ping_ms,
+            server_id: result.server_id.clone(),
+            jitter_ms: result.jitter_ms,
+            ttfb_ms: result.ttfb_ms,
+            packet_loss_percent: result.packet_loss_percent,
+            loaded_ping_ms: result.loaded_ping_ms,
+            download_streams: result.download_streams,
+            download_truncated: result.download_truncated,
+            retries: result.retries,
+            http_version: result.http_version.clone(),
+            download_histogram: result
+                .download_histogram
+                .iter()
+                .flatten()
+                .map(|bucket| HistogramBucketMessage { bucket_ms: bucket.bucket_ms, mbps: bucket.mbps })
+                .collect(),
+            efficiency_percent: result.efficiency_percent,
+            unreliable: result.unreliable,
+            download_checksum_valid: result.download_checksum_valid,
+            signature: result.signature.clone(),
+            cold_connections: result.cold_connections,
+            cached: result.cached,
+            download_burst_mbps: result.download_burst_mbps,
+            download_sustained_mbps: result.download_sustained_mbps,
+            download_cache_suspected: result.download_cache_suspected,
+            download_steady_mbps: result.download_steady_mbps,
+            egress_interface: result.egress_interface.clone(),
+            final_url: result.final_url.clone(),
+            asn: result.asn,
+            isp: result.isp.clone(),
+            upload_loaded_ping_ms: result.upload_loaded_ping_ms,
+            jitter_hosts: result.jitter_hosts.clone(),
+            download_size_mb: result.download_size_mb,
+            download_within_target: result.download_within_target,
+            latency_outliers: result.latency_outliers,
+            jitter_outliers: result.jitter_outliers,
+            capacity_mbps: result.capacity_mbps,
+            capacity_probes_mbps: result.capacity_probes_mbps.clone(),
+            upload_mode: result.upload_mode.clone(),
+            connection_profile: result.connection_profile.as_ref().map(|profile| ConnectionProfileMessage {
+                dns_ms: profile.dns_ms,
+                tcp_connect_ms: profile.tcp_connect_ms,
+                tls_ms: profile.tls_ms,
+                ttfb_ms: profile.ttfb_ms,
+                total_ms: profile.total_ms,
+            }),
+        }
+    }
+}
+
+/// Encodes every result as a length-delimited `SpeedTestResult` message.
+pub fn encode(results: &[SpeedTestResult]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for result in results {
+        ResultMessage::from(result).encode_length_delimited(&mut buf).unwrap();
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_decode_back_in_order() {
+        let results = ["us", "eu"].map(|id| SpeedTestResult {
+            server_id: id.to_string(),
+            download_speed_mbps: 250.0,
+            efficiency_percent: Some(25.0),
+            ..Default::default()
+        });
+        let mut buf = &encode(&results)[..];
+
+        let first = ResultMessage::decode_length_delimited(&mut buf).unwrap();
+        let second = ResultMessage::decode_length_delimited(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!((first.server_id.as_str(), second.server_id.as_str()), ("us", "eu"));
+        assert_eq!(first.download_speed_mbps, 250.0);
+        assert_eq!(first.efficiency_percent, Some(25.0));
+        assert_eq!(first.packet_loss_percent, None);
+    }
+}
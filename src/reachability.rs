@@ -0,0 +1,125 @@
+use crate::{dns::FallbackResolver, interface};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+/// How long a connect attempt may take before the family counts as unreachable.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Which address families of a test server accept connections. `None` means
+/// the family wasn't tried: the host has no address of that family (no A or
+/// AAAA record), or the `bound` local address is of the other one.
+#[derive(Debug, PartialEq)]
+pub struct Reachability {
+    pub host: String,
+    pub ipv4: Option<bool>,
+    pub ipv6: Option<bool>,
+    pub bound: Option<IpAddr>,
+}
+
+impl Reachability {
+    /// Resolves the host of `url` with `resolver` and tries a TCP connection
+    /// over each address family it resolves to, leaving through `interface`
+    /// and `local` like the test traffic.
+    pub async fn check(
+        url: &str,
+        resolver: &FallbackResolver,
+        interface: Option<&str>,
+        local: Option<IpAddr>,
+    ) -> Result<Self, String> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+        // IPv6 literals keep their brackets in the URL but not for resolution.
+        let host = match parsed.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            None => return Err(format!("{} has no host", url)),
+        };
+        let port = parsed.port_or_known_default().unwrap_or(443);
+        let addrs = resolver.addresses(&host).await.map_err(|e| e.to_string())?;
+
+        // A bound local address can only reach servers of its own family.
+        let family = |ipv6: bool| {
+            let usable = local.is_none_or(|local| local.is_ipv6() == ipv6);
+            let addr = addrs.iter().find(|addr| addr.is_ipv6() == ipv6).filter(|_| usable);
+            addr.map(|addr| SocketAddr::new(addr.ip(), port))
+        };
+        let connects = |addr: Option<SocketAddr>| async move {
+            let connect = interface::connect(addr?, interface, local);
+            Some(matches!(tokio::time::timeout(CONNECT_TIMEOUT, connect).await, Ok(Ok(_))))
+        };
+        let (ipv4, ipv6) = tokio::join!(connects(family(false)), connects(family(true)));
+        Ok(Reachability { host, ipv4, ipv6, bound: local })
+    }
+
+    /// `ipv4_reachable=…, ipv6_reachable=…`, for verbose output.
+    pub fn describe(&self) -> String {
+        let state = |reachable: Option<bool>, ipv6: bool, record| match (reachable, self.bound) {
+            (Some(reachable), _) => reachable.to_string(),
+            (None, Some(bound)) if bound.is_ipv6() != ipv6 => format!("false (not tried from {})", bound),
+            (None, _) => format!("false (no {} record)", record),
+        };
+        format!(
+            "{}: ipv4_reachable={}, ipv6_reachable={}",
+            self.host,
+            state(self.ipv4, false, "A"),
+            state(self.ipv6, true, "AAAA")
+        )
+    }
+
+    /// A warning when IPv6 resolves but doesn't connect, which makes clients
+    /// wait for the IPv6 attempt before falling back to IPv4.
+    pub fn warning(&self) -> Option<String> {
+        (self.ipv6 == Some(false)).then(|| {
+            format!(
+                "Warning: {} resolves to IPv6 but IPv6 connections fail; connections may stall before \
+                 falling back to IPv4, adding latency to the results",
+                self.host
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_the_families_that_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        let resolver = FallbackResolver::new(false);
+
+        let reachability = Reachability::check(&url, &resolver, None, None).await.unwrap();
+        let expected = Reachability { host: "127.0.0.1".to_string(), ipv4: Some(true), ipv6: None, bound: None };
+        assert_eq!(reachability, expected);
+        assert_eq!(reachability.describe(), "127.0.0.1: ipv4_reachable=true, ipv6_reachable=false (no AAAA record)");
+        assert_eq!(reachability.warning(), None);
+    }
+
+    #[tokio::test]
+    async fn connects_from_the_bound_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+        let resolver = FallbackResolver::new(false);
+
+        let bound = Reachability::check(&url, &resolver, None, Some("127.0.0.1".parse().unwrap())).await.unwrap();
+        assert_eq!(bound.ipv4, Some(true));
+        // An IPv6 source can't reach the server's IPv4 address, so that family isn't tried.
+        let other_family = Reachability::check(&url, &resolver, None, Some("::1".parse().unwrap())).await.unwrap();
+        assert_eq!((other_family.ipv4, other_family.ipv6), (None, None));
+        assert!(
+            other_family.describe().contains("ipv4_reachable=false (not tried from ::1)"),
+            "{}",
+            other_family.describe()
+        );
+        let no_interface = Reachability::check(&url, &resolver, Some("speedtest-no-such0"), None).await.unwrap();
+        assert_eq!(no_interface.ipv4, Some(false));
+    }
+
+    #[test]
+    fn warns_when_ipv6_resolves_but_fails() {
+        let broken = Reachability { host: "example.com".to_string(), ipv4: Some(true), ipv6: Some(false), bound: None };
+        assert!(broken.warning().unwrap().contains("example.com resolves to IPv6"));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{output, SpeedTestResult};
+
+/// A `--require` expression: conditions on result fields joined by `&&`
+/// and `||`, where `&&` binds tighter.
+#[derive(Clone, Debug)]
+pub struct Requirement {
+    /// Alternatives, any of which passing satisfies the requirement.
+    any_of: Vec<Vec<Condition>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Condition {
+    text: String,
+    field: String,
+    op: Op,
+    value: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Ge,
+    Le,
+    Gt,
+    Lt,
+    Eq,
+    Ne,
+}
+
+/// Longer operators first, so `>=` isn't read as `>`.
+const OPERATORS: [(&str, Op); 6] =
+    [(">=", Op::Ge), ("<=", Op::Le), ("==", Op::Eq), ("!=", Op::Ne), (">", Op::Gt), ("<", Op::Lt)];
+
+impl Op {
+    fn holds(self, actual: f64, expected: f64) -> bool {
+        match self {
+            Op::Ge => actual >= expected,
+            Op::Le => actual <= expected,
+            Op::Gt => actual > expected,
+            Op::Lt => actual < expected,
+            Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+        }
+    }
+}
+
+impl Requirement {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields = serde_json::to_value(SpeedTestResult::default()).unwrap();
+        let any_of = expression
+            .split("||")
+            .map(|all_of| all_of.split("&&").map(|text| Condition::parse(text.trim(), &fields)).collect())
+            .collect::<Result<Vec<Vec<_>>, _>>()?;
+        Ok(Requirement { any_of })
+    }
+
+    /// The conditions `result` fails, each with the measured value, or
+    /// nothing if the requirement is met.
+    pub fn failures(&self, result: &SpeedTestResult) -> Vec<String> {
+        let fields = serde_json::to_value(result).unwrap();
+        let actual = |condition: &Condition| fields.get(&condition.field).and_then(|v| v.as_f64()).unwrap_or(0.0);
+        if self.any_of.iter().any(|all_of| all_of.iter().all(|c| c.op.holds(actual(c), c.value))) {
+            return Vec::new();
+        }
+        self.any_of
+            .iter()
+            .flatten()
+            .filter(|c| !c.op.holds(actual(c), c.value))
+            .map(|c| format!("{} ({} = {:.2})", c.text, c.field, actual(c)))
+            .collect()
+    }
+}
+
+impl Condition {
+    fn parse(text: &str, fields: &serde_json::Value) -> Result<Self, String> {
+        let (index, symbol, op) = OPERATORS
+            .iter()
+            .filter_map(|&(symbol, op)| text.find(symbol).map(|index| (index, symbol, op)))
+            .min_by_key(|&(index, symbol, _)| (index, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(|| format!("'{}' has no comparison operator", text))?;
+
+        let name = text[..index].trim();
+        let field = output::field_name(name);
+        if !fields.get(field).is_some_and(|value| value.is_number()) {
+            return Err(format!("'{}' is not a numeric result field", name));
+        }
+        let value = text[index + symbol.len()..]
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' does not compare against a number", text))?;
+        Ok(Condition { text: text.to_string(), field: field.to_string(), op, value })
+    }
+}
+
+/// An `--assert-isp` expectation: an AS number (`7922` or `AS7922`), or
+/// part of the ISP's name, matched case-insensitively.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpectedIsp {
+    Asn(u32),
+    Name(String),
+}
+
+impl ExpectedIsp {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("the expected ISP is empty".to_string());
+        }
+        let digits = text.strip_prefix("AS").or_else(|| text.strip_prefix("as")).unwrap_or(text);
+        Ok(match digits.parse() {
+            Ok(asn) => ExpectedIsp::Asn(asn),
+            Err(_) => ExpectedIsp::Name(text.to_string()),
+        })
+    }
+
+    /// Why `result` wasn't measured on the expected network, or nothing if it was.
+    pub fn failure(&self, result: &SpeedTestResult) -> Option<String> {
+        let matches = match self {
+            ExpectedIsp::Asn(asn) => result.asn == Some(*asn),
+            ExpectedIsp::Name(name) => {
+                result.isp.as_ref().is_some_and(|isp| isp.to_lowercase().contains(&name.to_lowercase()))
+            }
+        };
+        let expected = match self {
+            ExpectedIsp::Asn(asn) => format!("AS{}", asn),
+            ExpectedIsp::Name(name) => name.clone(),
+        };
+        match (matches, result.asn, &result.isp) {
+            (true, _, _) => None,
+            (false, None, None) => Some(format!("expected {}, but the ISP could not be determined", expected)),
+            (false, asn, isp) => Some(format!(
+                "expected {}, but connected through {}",
+                expected,
+                [asn.map(|asn| format!("AS{}", asn)), isp.clone()].into_iter().flatten().collect::<Vec<_>>().join(" ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(download: f32, ping: f32) -> SpeedTestResult {
+        SpeedTestResult { download_speed_mbps: download, ping_ms: ping, ..Default::default() }
+    }
+
+    #[test]
+    fn all_conditions_must_hold() {
+        let requirement = Requirement::parse("download>=100 && ping <= 30").unwrap();
+        assert!(requirement.failures(&result(150.0, 20.0)).is_empty());
+
+        let failures = requirement.failures(&result(50.0, 20.0));
+        assert_eq!(failures, ["download>=100 (download_speed_mbps = 50.00)"]);
+    }
+
+    #[test]
+    fn any_alternative_may_hold() {
+        let requirement = Requirement::parse("download > 500 || ping < 10 && upload_speed_mbps != 0").unwrap();
+        assert!(requirement.failures(&result(600.0, 50.0)).is_empty());
+        assert_eq!(requirement.failures(&result(100.0, 5.0)).len(), 2);
+    }
+
+    #[test]
+    fn isp_is_matched_by_asn_or_name() {
+        let comcast = SpeedTestResult { asn: Some(7922), isp: Some("Comcast Cable".to_string()), ..Default::default() };
+        assert_eq!(ExpectedIsp::parse("AS7922").unwrap().failure(&comcast), None);
+        assert_eq!(ExpectedIsp::parse("comcast").unwrap().failure(&comcast), None);
+
+        let failure = ExpectedIsp::parse("Verizon").unwrap().failure(&comcast);
+        assert_eq!(failure.unwrap(), "expected Verizon, but connected through AS7922 Comcast Cable");
+        let unknown = ExpectedIsp::parse("701").unwrap().failure(&SpeedTestResult::default());
+        assert_eq!(unknown.unwrap(), "expected AS701, but the ISP could not be determined");
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_malformed_conditions() {
+        assert!(Requirement::parse("bandwidth >= 1").is_err());
+        assert!(Requirement::parse("server_id == 1").is_err());
+        assert!(Requirement::parse("download").is_err());
+        assert!(Requirement::parse("download >= fast").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use rand::Rng;
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// A retry budget shared by every phase (and exporter) of a run, so a
+/// flapping connection can't multiply the total test time unboundedly.
+pub struct RetryBudget {
+    remaining: Arc<AtomicU32>,
+    used: AtomicU32,
+    base: Duration,
+    verbose: bool,
+}
+
+impl RetryBudget {
+    pub fn new(budget: u32, base: Duration, verbose: bool) -> Self {
+        RetryBudget { remaining: Arc::new(AtomicU32::new(budget)), used: AtomicU32::new(0), base, verbose }
+    }
+
+    /// A budget drawing on the same remaining retries, for one test of the
+    /// run, that counts only the retries it uses itself.
+    pub fn share(&self) -> RetryBudget {
+        RetryBudget {
+            remaining: self.remaining.clone(),
+            used: AtomicU32::new(0),
+            base: self.base,
+            verbose: self.verbose,
+        }
+    }
+
+    /// Number of retries consumed so far.
+    pub fn used(&self) -> u32 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Runs `op`, retrying failures with jittered exponential backoff while
+    /// the budget lasts. The last error is returned once it's exhausted.
+    pub async fn run<T, E, F, Fut>(&self, label: &str, op: F) -> Result<T, E>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_while(label, |_| true, op).await
+    }
+
+    /// Like `run`, but only failures that `retryable` accepts are retried;
+    /// any other error is returned at once without using the budget.
+    pub async fn run_while<T, E, F, Fut>(&self, label: &str, retryable: impl Fn(&E) -> bool, mut op: F) -> Result<T, E>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    if !retryable(&e) || !self.take() {
+                        return Err(e);
+                    }
+                    let delay = self.backoff(attempt);
+                    if self.verbose {
+                        eprintln!("{} failed ({}), retrying in {}ms", label, e, delay.as_millis());
+                    }
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn take(&self) -> bool {
+        let taken = self.remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok();
+        if taken {
+            self.used.fetch_add(1, Ordering::Relaxed);
+        }
+        taken
+    }
+
+    /// Half of the exponential delay is fixed and half is random ("equal jitter").
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_BACKOFF);
+        let half = exponential / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// Whether a failed request may succeed on a second try: transport errors
+/// and server errors may, a client error other than a timeout or rate limit
+/// won't.
+pub fn is_transient(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) if status.is_client_error() => {
+            matches!(status, reqwest::StatusCode::REQUEST_TIMEOUT | reqwest::StatusCode::TOO_MANY_REQUESTS)
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn retries_until_success_within_budget() {
+        let budget = RetryBudget::new(3, Duration::from_millis(1), false);
+        let mut calls = 0;
+
+        let result: Result<u32, String> = budget
+            .run("op", || {
+                calls += 1;
+                let attempt = calls;
+                async move {
+                    if attempt < 3 {
+                        Err("boom".to_string())
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result, Ok(3));
+        assert_eq!(budget.used(), 2);
+    }
+
+    #[tokio::test]
+    async fn budget_is_shared_and_exhausts() {
+        let budget = RetryBudget::new(2, Duration::from_millis(1), false);
+        let failing = || async { Err::<(), _>("boom") };
+
+        assert!(budget.run("first", failing).await.is_err());
+        assert_eq!(budget.used(), 2);
+
+        // Nothing left for the next phase: it fails on the first error.
+        let mut calls = 0;
+        let _ = budget
+            .run("second", || {
+                calls += 1;
+                async { Err::<(), _>("boom") }
+            })
+            .await;
+        assert_eq!(calls, 1);
+        assert_eq!(budget.used(), 2);
+    }
+
+    #[tokio::test]
+    async fn shared_budgets_draw_on_one_total() {
+        let total = RetryBudget::new(3, Duration::from_millis(1), false);
+        let (first, second) = (total.share(), total.share());
+        let failing = || async { Err::<(), _>("boom") };
+
+        assert!(first.run("first", failing).await.is_err());
+        assert!(second.run("second", failing).await.is_err());
+
+        // Each test counts its own retries; together they stay within the total.
+        assert_eq!((first.used(), second.used()), (3, 0));
+    }
+
+    #[tokio::test]
+    async fn only_retryable_errors_use_the_budget() {
+        let budget = RetryBudget::new(3, Duration::from_millis(1), false);
+        let retryable = |e: &&str| *e == "503";
+        let mut calls = 0;
+
+        let result = budget
+            .run_while("op", retryable, || {
+                calls += 1;
+                let attempt = calls;
+                async move { Err::<(), _>(if attempt < 2 { "503" } else { "404" }) }
+            })
+            .await;
+
+        assert_eq!(result, Err("404"));
+        assert_eq!(budget.used(), 1);
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let budget = RetryBudget::new(0, Duration::from_millis(100), false);
+        let first = budget.backoff(0);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let late = budget.backoff(20);
+        assert!(late >= MAX_BACKOFF / 2 && late <= MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::interrupt;
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use indicatif::ProgressBar;
+use std::time::Duration;
+
+/// When `--run-at` should start the first test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunAt {
+    /// A wall-clock time in the local timezone, today or tomorrow.
+    Daily(NaiveTime),
+    /// A fixed instant.
+    At(DateTime<Utc>),
+}
+
+impl RunAt {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+            return Ok(RunAt::At(at.with_timezone(&Utc)));
+        }
+        NaiveTime::parse_from_str(value, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+            .map(RunAt::Daily)
+            .map_err(|_| "expected HH:MM, HH:MM:SS or an RFC3339 timestamp".to_string())
+    }
+
+    /// The first start time not before `now`. A daily time that has already
+    /// passed today moves to tomorrow; a past instant starts immediately.
+    pub fn next_occurrence<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> DateTime<Utc> {
+        match *self {
+            RunAt::At(at) => at.max(now.with_timezone(&Utc)),
+            RunAt::Daily(time) => {
+                let tz = now.timezone();
+                let mut day = now.date_naive();
+                loop {
+                    // A time skipped by a DST change has no local instant, so try the next day.
+                    if let Some(start) = tz.from_local_datetime(&day.and_time(time)).earliest() {
+                        if start > *now {
+                            return start.with_timezone(&Utc);
+                        }
+                    }
+                    day = day.succ_opt().unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// The start of the cycle after one scheduled at `previous`, keeping the
+/// schedule aligned to `previous` and skipping slots a long cycle overran.
+pub fn next_cycle(previous: DateTime<Utc>, interval: Duration, now: DateTime<Utc>) -> DateTime<Utc> {
+    let interval = chrono::Duration::from_std(interval).unwrap();
+    let next = previous + interval;
+    if next > now {
+        return next;
+    }
+    let missed = (now - next).num_milliseconds() / interval.num_milliseconds().max(1) + 1;
+    next + interval * missed as i32
+}
+
+/// Sleeps until `at`, showing the wait on the progress bar. Returns `false`
+/// if the wait was interrupted by Ctrl-C.
+pub async fn sleep_until(at: DateTime<Utc>, pb: &ProgressBar) -> bool {
+    let wait = (at - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+    pb.set_prefix("");
+    pb.set_message(format!("Waiting until {}...", at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")));
+    tokio::select! {
+        _ = tokio::time::sleep(wait) => true,
+        _ = interrupt::wait() => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn local(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    #[test]
+    fn parses_clock_times_and_instants() {
+        assert_eq!(RunAt::parse("07:30").unwrap(), RunAt::Daily(NaiveTime::from_hms_opt(7, 30, 0).unwrap()));
+        assert_eq!(RunAt::parse("07:30:15").unwrap(), RunAt::Daily(NaiveTime::from_hms_opt(7, 30, 15).unwrap()));
+        assert!(matches!(RunAt::parse("2026-01-02T03:04:05+02:00").unwrap(), RunAt::At(_)));
+        assert!(RunAt::parse("25:00").is_err());
+        assert!(RunAt::parse("soon").is_err());
+    }
+
+    #[test]
+    fn daily_time_that_has_passed_moves_to_tomorrow() {
+        let now = local("2026-03-10T12:00:00+02:00");
+        let later = RunAt::parse("13:00").unwrap().next_occurrence(&now);
+        let passed = RunAt::parse("11:00").unwrap().next_occurrence(&now);
+
+        assert_eq!(later, local("2026-03-10T13:00:00+02:00"));
+        assert_eq!(passed, local("2026-03-11T11:00:00+02:00"));
+    }
+
+    #[test]
+    fn past_instant_starts_now() {
+        let now = local("2026-03-10T12:00:00Z");
+        assert_eq!(RunAt::parse("2026-03-10T11:00:00Z").unwrap().next_occurrence(&now), now);
+    }
+
+    #[test]
+    fn next_cycle_stays_aligned_and_skips_overrun_slots() {
+        let start = local("2026-03-10T12:00:00Z").with_timezone(&Utc);
+        let hour = Duration::from_secs(3600);
+
+        let next = next_cycle(start, hour, start + chrono::Duration::minutes(5));
+        assert_eq!(next, local("2026-03-10T13:00:00Z"));
+
+        let overran = next_cycle(start, hour, start + chrono::Duration::minutes(150));
+        assert_eq!(overran, local("2026-03-10T15:00:00Z"));
+    }
+}
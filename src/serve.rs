@@ -0,0 +1,112 @@
+use bytes::Bytes;
+use futures_util::stream;
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, StreamBody};
+use hyper::{
+    body::{Frame, Incoming},
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+};
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+use tokio::net::TcpListener;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+static ZEROS: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+
+type Body = BoxBody<Bytes, Infallible>;
+
+/// Runs a minimal test server exposing the same endpoints as speed.cloudflare.com,
+/// so `--server-url http://<host>:<port>` can measure throughput against it.
+pub async fn serve(addr: SocketAddr) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Serving speed test endpoints on http://{}", listener.local_addr()?);
+
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                // Usually out of file descriptors: back off instead of spinning.
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        let service = service_fn(move |req| handle(req, remote));
+        let connection = graceful.watch(builder.serve_connection(TokioIo::new(stream), service).into_owned());
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+    }
+    graceful.shutdown().await;
+    Ok(())
+}
+
+async fn handle(req: Request<Incoming>, remote: SocketAddr) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/__down") => download(req.uri().query()),
+        (&Method::POST, "/__up") => upload(req.into_body()).await,
+        (&Method::GET, "/cdn-cgi/trace") => trace(&req, remote),
+        (&Method::GET, "/") => Response::new(full("ok")),
+        _ => status(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+fn download(query: Option<&str>) -> Response<Body> {
+    let bytes = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("bytes="))
+        .and_then(|value| value.parse::<u64>().ok());
+    let Some(total) = bytes else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+
+    let chunks = stream::iter((0..total).step_by(CHUNK_SIZE).map(move |offset| {
+        let len = (total - offset).min(CHUNK_SIZE as u64) as usize;
+        Ok::<_, Infallible>(Frame::data(Bytes::from_static(&ZEROS[..len])))
+    }));
+
+    Response::builder()
+        .header("content-length", total)
+        .header("content-type", "application/octet-stream")
+        .body(StreamBody::new(chunks).boxed())
+        .unwrap()
+}
+
+async fn upload(mut body: Incoming) -> Response<Body> {
+    while let Some(frame) = body.frame().await {
+        if frame.is_err() {
+            return status(StatusCode::BAD_REQUEST);
+        }
+    }
+    Response::new(Empty::new().boxed())
+}
+
+fn trace(req: &Request<Incoming>, remote: SocketAddr) -> Response<Body> {
+    let host = req.headers().get("host").and_then(|h| h.to_str().ok()).unwrap_or_default();
+    let body = format!(
+        "h={}\nip={}\nts={:.3}\nvisit_scheme=http\ncolo=LOCAL\n",
+        host,
+        remote.ip(),
+        chrono::Utc::now().timestamp_millis() as f64 / 1000.0,
+    );
+    Response::new(full(body))
+}
+
+fn full(body: impl Into<Bytes>) -> Body {
+    Full::new(body.into()).boxed()
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder().status(code).body(Empty::new().boxed()).unwrap()
+}
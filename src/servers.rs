@@ -0,0 +1,378 @@
+use futures_util::future::{select_ok, FutureExt};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Placeholder substituted with the requested size in download URL templates.
+pub const BYTES_PLACEHOLDER: &str = "{bytes}";
+/// Id of the default Cloudflare server, before the colo is known.
+const CLOUDFLARE_ID: &str = "cloudflare";
+
+/// Endpoints used by the individual test phases.
+pub struct Server {
+    pub id: String,
+    /// Download URL with a `{bytes}` placeholder for the requested size.
+    pub download_url_template: String,
+    pub upload_url: String,
+    pub latency_url: String,
+    /// Cloudflare-style trace endpoint, if the server has one.
+    pub trace_url: Option<String>,
+    /// Endpoint reporting the client's ASN and ISP, if the server has one.
+    pub meta_url: Option<String>,
+    pub expected_speed_mbps: Option<f64>,
+    /// Whether latency probes only ask for the headers, because the
+    /// latency URL is a whole file (`--download-url`).
+    pub head_latency: bool,
+}
+
+impl Server {
+    pub fn cloudflare() -> Self {
+        Server {
+            id: CLOUDFLARE_ID.to_string(),
+            download_url_template: "https://speed.cloudflare.com/__down?bytes={bytes}".to_string(),
+            upload_url: "https://speed.cloudflare.com/__up".to_string(),
+            // The download host, so every phase shares one DNS answer; the trace is an IP literal.
+            latency_url: "https://speed.cloudflare.com/__down?bytes=0".to_string(),
+            trace_url: Some("https://1.1.1.1/cdn-cgi/trace".to_string()),
+            meta_url: Some("https://speed.cloudflare.com/meta".to_string()),
+            expected_speed_mbps: None,
+            head_latency: false,
+        }
+    }
+
+    pub fn custom(base_url: &str) -> Self {
+        let base = base_url.trim_end_matches('/');
+        Server {
+            id: base.to_string(),
+            download_url_template: format!("{}/__down?bytes={}", base, BYTES_PLACEHOLDER),
+            upload_url: format!("{}/__up", base),
+            latency_url: format!("{}/", base),
+            trace_url: Some(format!("{}/cdn-cgi/trace", base)),
+            meta_url: None,
+            expected_speed_mbps: None,
+            head_latency: false,
+        }
+    }
+
+    /// A plain file download (`--download-url`): the whole file is fetched
+    /// whatever size is requested, latency is a HEAD of the file, and there
+    /// is no trace or upload endpoint.
+    pub fn file(url: &str) -> Self {
+        Server {
+            id: url.to_string(),
+            download_url_template: url.to_string(),
+            upload_url: String::new(),
+            latency_url: url.to_string(),
+            trace_url: None,
+            meta_url: None,
+            expected_speed_mbps: None,
+            head_latency: true,
+        }
+    }
+
+    /// A request timing one round trip to `url`: a GET, or a HEAD when `url` is
+    /// the latency URL of a file server, which a GET would download in full.
+    pub fn probe(&self, client: &Client, url: &str) -> RequestBuilder {
+        match self.head_latency && url == self.latency_url {
+            true => client.head(url),
+            false => client.get(url),
+        }
+    }
+
+    /// The host names the phases connect to, each once; IP literals need no lookup.
+    pub fn hostnames(&self) -> Vec<String> {
+        let urls = [
+            Some(&self.download_url_template),
+            Some(&self.upload_url),
+            Some(&self.latency_url),
+            self.trace_url.as_ref(),
+            self.meta_url.as_ref(),
+        ];
+        let mut hosts: Vec<String> = Vec::new();
+        for url in urls.into_iter().flatten().filter_map(|url| reqwest::Url::parse(url).ok()) {
+            if let Some(host) = url.domain().filter(|host| !hosts.iter().any(|known| known == host)) {
+                hosts.push(host.to_string());
+            }
+        }
+        hosts
+    }
+
+    /// URL for downloading `bytes` bytes.
+    pub fn download_url(&self, bytes: u64) -> String {
+        self.download_url_template.replace(BYTES_PLACEHOLDER, &bytes.to_string())
+    }
+
+    /// The id to report for a run whose trace returned `trace`: Cloudflare
+    /// results are named after the serving colo (e.g. `cloudflare-SFO`), any
+    /// other server keeps its configured id.
+    pub fn id_from_trace(&self, trace: &[(String, String)]) -> String {
+        let colo = trace.iter().find(|(key, _)| key == "colo").map(|(_, colo)| colo);
+        match colo {
+            Some(colo) if self.id == CLOUDFLARE_ID && !colo.is_empty() => format!("{}-{}", CLOUDFLARE_ID, colo),
+            _ => self.id.clone(),
+        }
+    }
+}
+
+/// Fetches the server's trace endpoint (`key=value` lines such as `colo`,
+/// `loc`, `ip` and `warp`), in the order the server sent them; empty if the
+/// server has no trace endpoint.
+pub async fn fetch_trace(client: &Client, server: &Server) -> Result<Vec<(String, String)>, reqwest::Error> {
+    let Some(trace_url) = &server.trace_url else { return Ok(Vec::new()) };
+    let body = client.get(trace_url).send().await?.error_for_status()?.text().await?;
+    Ok(parse_trace(&body))
+}
+
+/// The network the client connects from, as the server sees it.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct NetworkInfo {
+    pub asn: Option<u32>,
+    #[serde(rename = "asOrganization")]
+    pub isp: Option<String>,
+}
+
+/// Asks the server's meta endpoint which network the client is on; `None`
+/// if the server has no such endpoint or the lookup fails.
+pub async fn fetch_network(client: &Client, server: &Server) -> Option<NetworkInfo> {
+    let response = client.get(server.meta_url.as_ref()?).send().await.ok()?.error_for_status().ok()?;
+    serde_json::from_slice(&response.bytes().await.ok()?).ok()
+}
+
+fn parse_trace(body: &str) -> Vec<(String, String)> {
+    body.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// One entry of the `--servers-config` YAML file.
+#[derive(Deserialize)]
+struct ServerEntry {
+    url: String,
+    label: String,
+    #[serde(default, alias = "expected-speed")]
+    expected_speed: Option<f64>,
+}
+
+/// Loads the servers listed in a YAML file, optionally keeping only the one
+/// with the given label. Each server's id is its label.
+pub fn load_config(path: &Path, label: Option<&str>) -> Result<Vec<Server>, Box<dyn Error>> {
+    let entries: Vec<ServerEntry> = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    servers_from_entries(entries, label)
+}
+
+fn servers_from_entries(entries: Vec<ServerEntry>, label: Option<&str>) -> Result<Vec<Server>, Box<dyn Error>> {
+    let servers: Vec<Server> = entries
+        .into_iter()
+        .filter(|entry| label.is_none_or(|label| entry.label == label))
+        .map(|entry| Server {
+            id: entry.label,
+            expected_speed_mbps: entry.expected_speed,
+            ..Server::custom(&entry.url)
+        })
+        .collect();
+
+    match (servers.is_empty(), label) {
+        (true, Some(label)) => Err(format!("no server labelled '{}'", label).into()),
+        (true, None) => Err("no servers configured".into()),
+        (false, _) => Ok(servers),
+    }
+}
+
+/// Keeps the servers whose labels are listed, in the order listed.
+pub fn select_labels(servers: Vec<Server>, labels: &[String]) -> Result<Vec<Server>, Box<dyn Error>> {
+    let mut servers: Vec<Option<Server>> = servers.into_iter().map(Some).collect();
+    labels
+        .iter()
+        .map(|label| {
+            servers
+                .iter_mut()
+                .find(|server| server.as_ref().is_some_and(|server| &server.id == label))
+                .and_then(Option::take)
+                .ok_or_else(|| format!("no server labelled '{}'", label).into())
+        })
+        .collect()
+}
+
+/// Probes every server's latency endpoint at once and returns the index of
+/// the first to answer along with its round-trip time. Slower probes are
+/// dropped, and a probe that takes longer than `timeout` counts as a failure.
+pub async fn ping_race(client: &Client, servers: &[Server], timeout: Duration) -> Result<(usize, Duration), String> {
+    if servers.is_empty() {
+        return Err("no servers configured".to_string());
+    }
+    let probes = servers.iter().enumerate().map(|(index, server)| {
+        async move {
+            let start = Instant::now();
+            server.probe(client, &server.latency_url).timeout(timeout).send().await.map(|_| (index, start.elapsed()))
+        }
+        .boxed()
+    });
+
+    match select_ok(probes).await {
+        Ok((winner, _)) => Ok(winner),
+        Err(e) => Err(format!("no server reachable (last error: {})", e)),
+    }
+}
+
+/// Remembers the server picked by `ping_race` so recurring runs don't race
+/// every cycle. The choice expires after `ttl` or when it is invalidated.
+pub struct ServerCache {
+    ttl: Duration,
+    selected: Option<(usize, Instant)>,
+}
+
+impl ServerCache {
+    pub fn new(ttl: Duration) -> Self {
+        ServerCache { ttl, selected: None }
+    }
+
+    /// The cached server index, unless it has expired.
+    pub fn get(&self) -> Option<usize> {
+        self.selected.filter(|(_, selected_at)| selected_at.elapsed() < self.ttl).map(|(index, _)| index)
+    }
+
+    pub fn store(&mut self, index: usize) {
+        self.selected = Some((index, Instant::now()));
+    }
+
+    pub fn invalidate(&mut self) {
+        self.selected = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn every_cloudflare_phase_resolves_the_same_host() {
+        assert_eq!(Server::cloudflare().hostnames(), ["speed.cloudflare.com"]);
+        assert!(Server::custom("http://10.0.0.5:8080").hostnames().is_empty());
+    }
+
+    #[tokio::test]
+    async fn network_info_comes_from_the_meta_endpoint() {
+        let mock = MockServer::start().await;
+        let meta = serde_json::json!({"asn": 7922, "asOrganization": "Comcast Cable", "colo": "SJC"});
+        Mock::given(any()).respond_with(ResponseTemplate::new(200).set_body_json(meta)).mount(&mock).await;
+        let server = Server { meta_url: Some(format!("{}/meta", mock.uri())), ..Server::custom(&mock.uri()) };
+
+        let network = fetch_network(&Client::new(), &server).await.unwrap();
+        assert_eq!(network, NetworkInfo { asn: Some(7922), isp: Some("Comcast Cable".to_string()) });
+        assert_eq!(fetch_network(&Client::new(), &Server::custom(&mock.uri())).await, None);
+    }
+
+    async fn server_with_delay(delay: Duration) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(any()).respond_with(ResponseTemplate::new(200).set_delay(delay)).mount(&server).await;
+        server
+    }
+
+    #[test]
+    fn cloudflare_results_are_named_after_the_colo() {
+        let trace = parse_trace("fl=29f\nh=speed.cloudflare.com\nip=203.0.113.7\ncolo=SFO\nloc=US\nwarp=off\n");
+        assert_eq!(trace[3], ("colo".to_string(), "SFO".to_string()));
+        assert_eq!(trace.len(), 6);
+        assert_eq!(Server::cloudflare().id_from_trace(&trace), "cloudflare-SFO");
+        assert_eq!(Server::cloudflare().id_from_trace(&[]), "cloudflare");
+        assert_eq!(Server::custom("http://10.0.0.1:8080").id_from_trace(&trace), "http://10.0.0.1:8080");
+    }
+
+    #[tokio::test]
+    async fn ping_race_picks_the_first_responder() {
+        let slow = server_with_delay(Duration::from_millis(500)).await;
+        let fast = server_with_delay(Duration::ZERO).await;
+        let servers = vec![Server::custom(&slow.uri()), Server::custom(&fast.uri())];
+
+        let (winner, rtt) = ping_race(&Client::new(), &servers, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(servers[winner].id, fast.uri());
+        assert!(rtt < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn ping_race_fails_when_nothing_answers() {
+        let servers = vec![Server::custom("http://127.0.0.1:9"), Server::custom("http://127.0.0.1:9/other")];
+        let err = ping_race(&Client::new(), &servers, Duration::from_secs(2)).await.unwrap_err();
+        assert!(err.starts_with("no server reachable"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn ping_race_gives_up_on_servers_slower_than_the_probe_timeout() {
+        let slow = server_with_delay(Duration::from_secs(5)).await;
+        let start = Instant::now();
+        let err =
+            ping_race(&Client::new(), &[Server::custom(&slow.uri())], Duration::from_millis(200)).await.unwrap_err();
+        assert!(err.starts_with("no server reachable"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn server_cache_expires_and_invalidates() {
+        let mut cache = ServerCache::new(Duration::from_secs(3600));
+        assert_eq!(cache.get(), None);
+        cache.store(2);
+        assert_eq!(cache.get(), Some(2));
+        cache.invalidate();
+        assert_eq!(cache.get(), None);
+
+        let mut expired = ServerCache::new(Duration::ZERO);
+        expired.store(1);
+        assert_eq!(expired.get(), None);
+    }
+
+    const CONFIG: &str = "
+- url: https://fra.example.com/
+  label: frankfurt
+  expected-speed: 500
+- url: https://nyc.example.com
+  label: new-york
+";
+
+    #[test]
+    fn parses_servers_and_tags_them_with_labels() {
+        let entries = serde_yaml::from_str(CONFIG).unwrap();
+        let servers = servers_from_entries(entries, None).unwrap();
+
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].id, "frankfurt");
+        assert_eq!(servers[0].download_url(1000), "https://fra.example.com/__down?bytes=1000");
+        assert_eq!(servers[0].expected_speed_mbps, Some(500.0));
+        assert_eq!(servers[1].expected_speed_mbps, None);
+    }
+
+    #[test]
+    fn selects_server_by_label() {
+        let entries = serde_yaml::from_str(CONFIG).unwrap();
+        let servers = servers_from_entries(entries, Some("new-york")).unwrap();
+
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].trace_url.as_deref(), Some("https://nyc.example.com/cdn-cgi/trace"));
+    }
+
+    #[test]
+    fn selects_several_labels_in_order() {
+        let entries = serde_yaml::from_str(CONFIG).unwrap();
+        let servers = servers_from_entries(entries, None).unwrap();
+        let labels = ["new-york", "frankfurt"].map(String::from);
+
+        let selected = select_labels(servers, &labels).unwrap();
+        assert_eq!(selected.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), labels);
+
+        let entries = serde_yaml::from_str(CONFIG).unwrap();
+        let servers = servers_from_entries(entries, None).unwrap();
+        assert!(select_labels(servers, &["tokyo".to_string()]).is_err());
+    }
+
+    #[test]
+    fn unknown_label_is_an_error() {
+        let entries = serde_yaml::from_str(CONFIG).unwrap();
+        assert!(servers_from_entries(entries, Some("tokyo")).is_err());
+    }
+}
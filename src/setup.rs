@@ -0,0 +1,99 @@
+use crate::{config, parse_nonzero_duration};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Password, Select};
+use std::{io::IsTerminal, path::Path};
+
+/// `speedtest-cli setup`: asks for the options most setups need (test server,
+/// interval, export backend and its credentials) and writes them to `path`,
+/// which later runs read as their defaults.
+pub fn run(path: &Path) -> Result<(), String> {
+    if !std::io::stdin().is_terminal() {
+        return Err("setup asks questions, so it needs an interactive terminal".to_string());
+    }
+    let theme = ColorfulTheme::default();
+    if path.exists() {
+        let replace = Confirm::with_theme(&theme)
+            .with_prompt(format!("{} already exists. Replace it?", path.display()))
+            .default(false)
+            .interact()
+            .map_err(|e| e.to_string())?;
+        if !replace {
+            println!("Left {} unchanged", path.display());
+            return Ok(());
+        }
+    }
+
+    let mut table = toml::Table::new();
+    prompt(&theme, &mut table).map_err(|e| e.to_string())?;
+    config::save(path, &table)?;
+    match Some(path) == crate::default_config_path().as_deref() {
+        true => {
+            println!("Wrote {}; speedtest-cli now uses these settings unless a flag overrides them", path.display())
+        }
+        false => println!("Wrote {}; pass --config {} to runs so they read it", path.display(), path.display()),
+    }
+    Ok(())
+}
+
+fn prompt(theme: &ColorfulTheme, table: &mut toml::Table) -> dialoguer::Result<()> {
+    let mut set = |key: &str, value: String| {
+        table.insert(key.to_string(), toml::Value::String(value));
+    };
+
+    let server = Select::with_theme(theme)
+        .with_prompt("Test server")
+        .items(&["Cloudflare (the default)", "My own server (Cloudflare-compatible endpoints)"])
+        .default(0)
+        .interact()?;
+    if server == 1 {
+        let url: String = Input::with_theme(theme)
+            .with_prompt("Server URL")
+            .validate_with(|url: &String| match url.starts_with("http://") || url.starts_with("https://") {
+                true => Ok(()),
+                false => Err("the URL must start with http:// or https://"),
+            })
+            .interact_text()?;
+        set("server-url", url);
+    }
+
+    let interval: String = Input::with_theme(theme)
+        .with_prompt("Test every (e.g. 15m, 1h; leave empty to test once per run)")
+        .allow_empty(true)
+        .validate_with(|interval: &String| match interval.is_empty() {
+            true => Ok(()),
+            false => parse_nonzero_duration(interval).map(|_| ()),
+        })
+        .interact_text()?;
+    if !interval.is_empty() {
+        set("interval", interval);
+    }
+
+    let backend = Select::with_theme(theme)
+        .with_prompt("Export results to")
+        .items(&["Nowhere, just print them", "InfluxDB 1.x", "ClickHouse"])
+        .default(0)
+        .interact()?;
+    let text = |prompt: &str, default: &str| {
+        Input::<String>::with_theme(theme).with_prompt(prompt).default(default.to_string())
+    };
+    match backend {
+        1 => {
+            set("influxdb-v1-url", text("InfluxDB URL", "http://localhost:8086").interact_text()?);
+            set("influxdb-v1-db", text("Database", "speedtest").interact_text()?);
+            let user = text("User (leave empty without authentication)", "").allow_empty(true).interact_text()?;
+            if !user.is_empty() {
+                set("influxdb-v1-user", user);
+                set("influxdb-v1-password", Password::with_theme(theme).with_prompt("Password").interact()?);
+            }
+        }
+        2 => {
+            set("clickhouse-url", text("ClickHouse URL", "http://localhost:8123").interact_text()?);
+            set("clickhouse-db", text("Database", "default").interact_text()?);
+            set("clickhouse-user", text("User", "default").interact_text()?);
+            let password = Password::with_theme(theme).with_prompt("Password").allow_empty_password(true).interact()?;
+            // Written even when empty: any ClickHouse setting makes the password required.
+            set("clickhouse-password", password);
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::SpeedTestResult;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::Path;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Reads an HMAC key file. Trailing whitespace is ignored, so a key written
+/// with `echo` or `openssl rand -hex 32 >` works as is.
+pub fn read_key(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut key = std::fs::read(path)?;
+    while key.last().is_some_and(u8::is_ascii_whitespace) {
+        key.pop();
+    }
+    Ok(key)
+}
+
+/// The hex HMAC-SHA256 of `result` serialized as JSON without its signature.
+pub fn sign(key: &[u8], result: &SpeedTestResult) -> String {
+    format!("{:x}", mac(key, result).finalize().into_bytes())
+}
+
+/// Whether `result` carries a valid signature for `key`; `None` if unsigned.
+pub fn verify(key: &[u8], result: &SpeedTestResult) -> Option<bool> {
+    let signature = result.signature.as_deref()?;
+    let Some(bytes) = decode_hex(signature) else { return Some(false) };
+    Some(mac(key, result).verify_slice(&bytes).is_ok())
+}
+
+fn mac(key: &[u8], result: &SpeedTestResult) -> HmacSha256 {
+    // The signature itself is skipped when unset, so it never covers itself;
+    // serving a result from the --cache-ttl cache doesn't invalidate it.
+    let unsigned = SpeedTestResult { signature: None, cached: false, ..result.clone() };
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&serde_json::to_vec(&unsigned).unwrap());
+    mac
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(key: &[u8]) -> SpeedTestResult {
+        let mut result =
+            SpeedTestResult { download_speed_mbps: 94.2, server_id: "cloudflare".to_string(), ..Default::default() };
+        result.signature = Some(sign(key, &result));
+        result
+    }
+
+    #[test]
+    fn signature_survives_a_json_round_trip() {
+        let json = serde_json::to_string_pretty(&signed(b"secret")).unwrap();
+        let parsed: SpeedTestResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(verify(b"secret", &parsed), Some(true));
+        assert_eq!(verify(b"other", &parsed), Some(false));
+        let cached = SpeedTestResult { cached: true, ..parsed };
+        assert_eq!(verify(b"secret", &cached), Some(true));
+    }
+
+    #[test]
+    fn edited_or_unsigned_results_fail() {
+        let edited = SpeedTestResult { download_speed_mbps: 500.0, ..signed(b"secret") };
+        assert_eq!(verify(b"secret", &edited), Some(false));
+        assert_eq!(verify(b"secret", &SpeedTestResult::default()), None);
+        let garbled = SpeedTestResult { signature: Some("not hex".to_string()), ..signed(b"secret") };
+        assert_eq!(verify(b"secret", &garbled), Some(false));
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+/// Largest download `--target-duration` will ask for, so a stalled transfer
+/// can't scale the next one out of proportion.
+const MAX_SIZE_MB: u32 = 10_000;
+
+/// Picks each server's download size so the download takes about `target`
+/// (`--target-duration`): every download that misses the band around the
+/// target rescales the size for the server's next iteration.
+pub struct DownloadSizer {
+    target: Duration,
+    /// Accepted deviation from the target, as a fraction of it.
+    tolerance: f64,
+    initial_mb: u32,
+    sizes: Mutex<HashMap<String, u32>>,
+}
+
+/// How a download's duration compared with the target.
+#[derive(Debug, PartialEq)]
+pub struct Sizing {
+    pub within_target: bool,
+    /// Size the next download from the same server will use.
+    pub next_mb: u32,
+}
+
+impl DownloadSizer {
+    pub fn new(target: Duration, tolerance_percent: f64, initial_mb: u32) -> Self {
+        DownloadSizer { target, tolerance: tolerance_percent / 100.0, initial_mb, sizes: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    /// Download size in MB for the next test of `server`.
+    pub fn size_for(&self, server: &str) -> u32 {
+        self.sizes.lock().unwrap().get(server).copied().unwrap_or(self.initial_mb)
+    }
+
+    /// Records that downloading `size_mb` from `server` took `took`, and
+    /// resizes the next download if that was outside the band.
+    pub fn record(&self, server: &str, size_mb: u32, took: Duration) -> Sizing {
+        let ratio = self.target.as_secs_f64() / took.as_secs_f64().max(0.001);
+        let within_target = (ratio.recip() - 1.0).abs() <= self.tolerance;
+        let next_mb = match within_target {
+            true => size_mb,
+            false => ((size_mb as f64 * ratio).round() as u32).clamp(1, MAX_SIZE_MB),
+        };
+        self.sizes.lock().unwrap().insert(server.to_string(), next_mb);
+        Sizing { within_target, next_mb }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_are_resized_towards_the_target() {
+        let sizer = DownloadSizer::new(Duration::from_secs(10), 25.0, 100);
+        assert_eq!(sizer.size_for("cloudflare"), 100);
+
+        let fast = sizer.record("cloudflare", 100, Duration::from_secs(2));
+        assert_eq!(fast, Sizing { within_target: false, next_mb: 500 });
+        assert_eq!(sizer.size_for("cloudflare"), 500);
+        assert_eq!(sizer.size_for("eu"), 100);
+
+        let close = sizer.record("cloudflare", 500, Duration::from_secs_f64(11.5));
+        assert_eq!(close, Sizing { within_target: true, next_mb: 500 });
+
+        let stalled = sizer.record("eu", 100, Duration::from_secs(400));
+        assert_eq!(stalled.next_mb, 3);
+        assert_eq!(sizer.record("eu", 3, Duration::from_millis(1)).next_mb, MAX_SIZE_MB);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{interrupt, servers::Server, summary::Stats};
+use indicatif::ProgressBar;
+use reqwest::Client;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Bytes fetched by each probe's download.
+const PROBE_DOWNLOAD_BYTES: u64 = 1_000_000;
+/// Time between the starts of consecutive probe rounds.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+/// A probe that hasn't finished after this long counts as a dropout.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One successful probe: a latency request followed by a small download.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Probe {
+    pub latency_ms: f64,
+    pub download_mbps: f64,
+}
+
+/// Stability of one server over a `--soak` run.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SoakReport {
+    pub server_id: String,
+    pub duration_secs: f64,
+    pub probes: usize,
+    /// Probes that failed or timed out.
+    pub dropouts: usize,
+    pub latency_ms: Stats,
+    /// Standard deviation over mean; 0 means perfectly steady.
+    pub latency_cv: f64,
+    /// How far the slowest probe was above the median latency.
+    pub worst_latency_spike_ms: f64,
+    pub download_speed_mbps: Stats,
+    pub download_cv: f64,
+}
+
+impl SoakReport {
+    /// Summarizes `probes`, where `None` is a dropout.
+    pub fn from_probes(server_id: &str, duration: Duration, probes: &[Option<Probe>]) -> Self {
+        let ok: Vec<Probe> = probes.iter().flatten().copied().collect();
+        let latency = Stats::from_samples(&ok.iter().map(|p| p.latency_ms).collect::<Vec<_>>());
+        let download = Stats::from_samples(&ok.iter().map(|p| p.download_mbps).collect::<Vec<_>>());
+        SoakReport {
+            server_id: server_id.to_string(),
+            duration_secs: duration.as_secs_f64(),
+            probes: probes.len(),
+            dropouts: probes.len() - ok.len(),
+            latency_ms: latency,
+            latency_cv: coefficient_of_variation(&latency),
+            worst_latency_spike_ms: latency.max - latency.median,
+            download_speed_mbps: download,
+            download_cv: coefficient_of_variation(&download),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        format!(
+            "Soak test of {} over {:.0}s: {} probes, {} dropouts\n\
+             Latency: mean {:.2} ms, CV {:.3}, worst spike +{:.2} ms (max {:.2} ms)\n\
+             Download: mean {:.2} Mbps, CV {:.3} (min {:.2}, max {:.2})",
+            self.server_id,
+            self.duration_secs,
+            self.probes,
+            self.dropouts,
+            self.latency_ms.mean,
+            self.latency_cv,
+            self.worst_latency_spike_ms,
+            self.latency_ms.max,
+            self.download_speed_mbps.mean,
+            self.download_cv,
+            self.download_speed_mbps.min,
+            self.download_speed_mbps.max,
+        )
+    }
+}
+
+fn coefficient_of_variation(stats: &Stats) -> f64 {
+    if stats.mean > 0.0 {
+        stats.stddev / stats.mean
+    } else {
+        0.0
+    }
+}
+
+/// Measures latency and a small download against `server`, or `None` if
+/// either fails or takes longer than the probe timeout.
+pub async fn probe(client: &Client, server: &Server) -> Option<Probe> {
+    let measure = async {
+        let start = Instant::now();
+        server.probe(client, &server.latency_url).send().await.ok()?.error_for_status().ok()?;
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let start = Instant::now();
+        let response = client.get(server.download_url(PROBE_DOWNLOAD_BYTES)).send().await.ok()?;
+        let body = response.error_for_status().ok()?.bytes().await.ok()?;
+        let download_mbps = body.len() as f64 * 8.0 / start.elapsed().as_secs_f64().max(f64::EPSILON) / 1_000_000.0;
+        Some(Probe { latency_ms, download_mbps })
+    };
+    tokio::time::timeout(PROBE_TIMEOUT, measure).await.ok().flatten()
+}
+
+/// Probes every server once per round until `duration` has passed (or the
+/// run is interrupted), then reports each server's stability.
+pub async fn run(client: &Client, servers: &[Server], duration: Duration, pb: &ProgressBar) -> Vec<SoakReport> {
+    let start = Instant::now();
+    let mut probes: Vec<Vec<Option<Probe>>> = vec![Vec::new(); servers.len()];
+    while start.elapsed() < duration && !interrupt::is_interrupted() {
+        let round = Instant::now();
+        for (server, probes) in servers.iter().zip(&mut probes) {
+            probes.push(probe(client, server).await);
+        }
+        let dropouts: usize = probes.iter().flatten().filter(|p| p.is_none()).count();
+        pb.set_message(format!(
+            "Soak test: {}s / {}s, {} dropouts",
+            start.elapsed().as_secs(),
+            duration.as_secs(),
+            dropouts
+        ));
+
+        let wait = PROBE_INTERVAL.saturating_sub(round.elapsed()).min(duration.saturating_sub(start.elapsed()));
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = interrupt::wait() => break,
+        }
+    }
+
+    let elapsed = start.elapsed();
+    servers.iter().zip(&probes).map(|(server, probes)| SoakReport::from_probes(&server.id, elapsed, probes)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn reports_variation_dropouts_and_the_worst_spike() {
+        let probe = |latency_ms, download_mbps| Some(Probe { latency_ms, download_mbps });
+        let probes = [probe(10.0, 100.0), None, probe(10.0, 100.0), probe(40.0, 100.0), probe(10.0, 100.0)];
+        let report = SoakReport::from_probes("local", Duration::from_secs(5), &probes);
+
+        assert_eq!((report.probes, report.dropouts), (5, 1));
+        assert_eq!(report.worst_latency_spike_ms, 30.0);
+        assert_eq!(report.latency_ms.mean, 17.5);
+        assert_eq!(report.latency_cv, 15.0 / 17.5);
+        assert_eq!(report.download_cv, 0.0);
+    }
+
+    #[test]
+    fn all_dropouts_report_zeroed_metrics() {
+        let report = SoakReport::from_probes("down", Duration::from_secs(2), &[None, None]);
+        assert_eq!(report.dropouts, 2);
+        assert_eq!((report.latency_cv, report.worst_latency_spike_ms), (0.0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn a_failed_download_is_a_dropout() {
+        let mock = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/")).respond_with(ResponseTemplate::new(200)).mount(&mock).await;
+        Mock::given(method("GET")).and(path("/__down")).respond_with(ResponseTemplate::new(503)).mount(&mock).await;
+
+        assert_eq!(probe(&Client::new(), &Server::custom(&mock.uri())).await, None);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::SpeedTestResult;
+use serde::Serialize;
+
+/// Aggregate statistics for one metric across iterations.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Stats { min: 0.0, max: 0.0, mean: 0.0, median: 0.0, stddev: 0.0 };
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let median = if n.is_multiple_of(2) { (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0 } else { sorted[n / 2] };
+        // Sample standard deviation; a single run has no spread.
+        let stddev =
+            if n > 1 { (sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt() } else { 0.0 };
+
+        Stats { min: sorted[0], max: sorted[n - 1], mean, median, stddev }
+    }
+}
+
+/// How many scaled median absolute deviations from the median a sample may
+/// lie before `without_outliers` drops it.
+const OUTLIER_MADS: f64 = 3.0;
+/// Smallest spread assumed, in ms, so ties in millisecond-resolution samples
+/// don't turn a 1ms difference into an outlier.
+const MIN_MAD_MS: f64 = 1.0;
+
+/// `samples` without outliers by the median absolute deviation (MAD), in
+/// their original order, and how many were dropped (`--exclude-outliers`).
+pub fn without_outliers(samples: &[f64]) -> (Vec<f64>, usize) {
+    let median = Stats::from_samples(samples).median;
+    let deviations: Vec<f64> = samples.iter().map(|sample| (sample - median).abs()).collect();
+    // 1.4826 makes the MAD estimate the standard deviation for normal data.
+    let spread = (Stats::from_samples(&deviations).median * 1.4826).max(MIN_MAD_MS);
+    let kept: Vec<f64> =
+        samples.iter().copied().filter(|sample| (sample - median).abs() <= OUTLIER_MADS * spread).collect();
+    let dropped = samples.len() - kept.len();
+    (kept, dropped)
+}
+
+/// Summary of a batch of iterations against one server.
+#[derive(Serialize)]
+pub struct Summary {
+    pub server_id: String,
+    pub iterations: usize,
+    pub download_speed_mbps: Stats,
+    pub upload_speed_mbps: Stats,
+    pub ping_ms: Stats,
+    pub jitter_ms: Stats,
+}
+
+impl Summary {
+    /// Summarizes results per server, in the order servers were first tested.
+    pub fn by_server(results: &[SpeedTestResult]) -> Vec<Self> {
+        let mut server_ids: Vec<&str> = Vec::new();
+        for result in results {
+            if !server_ids.contains(&result.server_id.as_str()) {
+                server_ids.push(&result.server_id);
+            }
+        }
+
+        server_ids
+            .into_iter()
+            .map(|server_id| {
+                let runs: Vec<&SpeedTestResult> = results.iter().filter(|r| r.server_id == server_id).collect();
+                Summary::from_results(server_id, &runs)
+            })
+            .collect()
+    }
+
+    fn from_results(server_id: &str, results: &[&SpeedTestResult]) -> Self {
+        let stats = |metric: fn(&SpeedTestResult) -> f32| {
+            Stats::from_samples(&results.iter().map(|r| metric(r) as f64).collect::<Vec<_>>())
+        };
+
+        Summary {
+            server_id: server_id.to_string(),
+            iterations: results.len(),
+            download_speed_mbps: stats(|r| r.download_speed_mbps),
+            upload_speed_mbps: stats(|r| r.upload_speed_mbps),
+            ping_ms: stats(|r| r.ping_ms),
+            jitter_ms: stats(|r| r.jitter_ms),
+        }
+    }
+
+    /// Metric name and statistics pairs, in display order.
+    pub fn metrics(&self) -> [(&'static str, &Stats); 4] {
+        [
+            ("download_speed_mbps", &self.download_speed_mbps),
+            ("upload_speed_mbps", &self.upload_speed_mbps),
+            ("ping_ms", &self.ping_ms),
+            ("jitter_ms", &self.jitter_ms),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outliers_are_dropped_by_median_absolute_deviation() {
+        assert_eq!(without_outliers(&[12.0, 250.0, 13.0]), (vec![12.0, 13.0], 1));
+        assert_eq!(without_outliers(&[12.0, 12.0, 13.0]), (vec![12.0, 12.0, 13.0], 0));
+        let (kept, dropped) = without_outliers(&[20.1, 21.4, 19.8, 20.6, 95.0, 20.9, 3.0]);
+        assert_eq!((kept, dropped), (vec![20.1, 21.4, 19.8, 20.6, 20.9], 2));
+        assert_eq!(without_outliers(&[]), (vec![], 0));
+    }
+
+    #[test]
+    fn stats_for_odd_sample_count() {
+        let stats = Stats::from_samples(&[3.0, 1.0, 2.0]);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 3.0);
+        assert_eq!(stats.mean, 2.0);
+        assert_eq!(stats.median, 2.0);
+        assert_eq!(stats.stddev, 1.0);
+    }
+
+    #[test]
+    fn stats_for_even_sample_count() {
+        let stats = Stats::from_samples(&[4.0, 1.0, 2.0, 3.0]);
+        assert_eq!(stats.median, 2.5);
+        assert_eq!(stats.mean, 2.5);
+    }
+
+    #[test]
+    fn stats_for_single_and_empty_samples() {
+        assert_eq!(Stats::from_samples(&[5.0]).stddev, 0.0);
+        assert_eq!(Stats::from_samples(&[]).mean, 0.0);
+    }
+}
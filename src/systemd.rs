@@ -0,0 +1,184 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Reports the daemon's state to systemd for `Type=notify` units: READY=1
+/// once the first cycle is done (or before waiting for it), then WATCHDOG=1
+/// as results come in and while waiting for the next cycle, so a hung cycle
+/// trips `WatchdogSec=`.
+pub struct Notifier {
+    /// The `NOTIFY_SOCKET` to send to; nothing is sent without one.
+    socket: Option<PathBuf>,
+    /// Half the unit's `WatchdogSec=`, if systemd set one.
+    watchdog: Option<Duration>,
+    ready: AtomicBool,
+}
+
+enum State<'a> {
+    Ready,
+    Status(&'a str),
+    Watchdog,
+    Stopping,
+}
+
+impl Notifier {
+    /// Enabled with `--systemd-notify`, or whenever systemd passes a
+    /// `NOTIFY_SOCKET`.
+    pub fn new(forced: bool) -> Self {
+        let socket = std::env::var_os("NOTIFY_SOCKET").map(PathBuf::from);
+        match forced || socket.is_some() {
+            true => Notifier::with_socket(socket, watchdog_timeout()),
+            false => Notifier::with_socket(None, None),
+        }
+    }
+
+    /// Sends to `socket`, with the watchdog pinged at half of
+    /// `watchdog_timeout` (the unit's `WatchdogSec=`).
+    pub fn with_socket(socket: Option<PathBuf>, watchdog_timeout: Option<Duration>) -> Self {
+        let watchdog = socket.as_ref().and(watchdog_timeout).map(|timeout| timeout / 2);
+        Notifier { socket, watchdog, ready: AtomicBool::new(false) }
+    }
+
+    /// Sends READY=1 before a wait for the first cycle (--run-at), so a
+    /// start that is hours away doesn't run into systemd's start timeout.
+    pub fn waiting(&self) {
+        if !self.ready.swap(true, Ordering::Relaxed) {
+            self.send(&[State::Ready, State::Status("Waiting for the first cycle")]);
+        }
+    }
+
+    /// Marks a cycle as complete: READY=1 the first time, and `status` for
+    /// `systemctl status`.
+    pub fn cycle_done(&self, status: &str) {
+        match self.ready.swap(true, Ordering::Relaxed) {
+            false => self.send(&[State::Ready, State::Status(status)]),
+            true => self.send(&[State::Status(status), State::Watchdog]),
+        }
+    }
+
+    /// Tells the watchdog the daemon is still making progress.
+    pub fn alive(&self) {
+        if self.watchdog.is_some() {
+            self.send(&[State::Watchdog]);
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.send(&[State::Stopping]);
+    }
+
+    /// Runs `future`, pinging the watchdog while it's pending. Only for waits
+    /// that can't hang, such as the sleep between cycles.
+    pub async fn alive_during<F: Future>(&self, future: F) -> F::Output {
+        let Some(every) = self.watchdog else { return future.await };
+        tokio::pin!(future);
+        loop {
+            tokio::select! {
+                output = &mut future => return output,
+                _ = tokio::time::sleep(every) => self.alive(),
+            }
+        }
+    }
+
+    fn send(&self, states: &[State]) {
+        if let Some(socket) = &self.socket {
+            // Nothing to do about a failure; systemd restarts us if it matters.
+            let _ = notify(socket, states);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn notify(socket: &Path, states: &[State]) -> std::io::Result<()> {
+    use sd_notify::NotifyState;
+    let message: String = states
+        .iter()
+        .map(|state| match state {
+            State::Ready => NotifyState::Ready,
+            State::Status(status) => NotifyState::Status(status),
+            State::Watchdog => NotifyState::Watchdog,
+            State::Stopping => NotifyState::Stopping,
+        })
+        .map(|state| format!("{}\n", state))
+        .collect();
+    std::os::unix::net::UnixDatagram::unbound()?.send_to_addr(message.as_bytes(), &address(socket)?).map(|_| ())
+}
+
+/// The address of `socket`, where a leading `@` names a socket in Linux's
+/// abstract namespace, as systemd passes it in some setups.
+#[cfg(target_os = "linux")]
+fn address(socket: &Path) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    use std::os::{linux::net::SocketAddrExt, unix::ffi::OsStrExt, unix::net::SocketAddr};
+    match socket.as_os_str().as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(socket),
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn address(socket: &Path) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    std::os::unix::net::SocketAddr::from_pathname(socket)
+}
+
+#[cfg(not(unix))]
+fn notify(_socket: &Path, _states: &[State]) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn watchdog_timeout() -> Option<Duration> {
+    sd_notify::watchdog_enabled()
+}
+
+#[cfg(not(unix))]
+fn watchdog_timeout() -> Option<Duration> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[tokio::test]
+    async fn readiness_and_watchdog_pings_reach_the_socket() {
+        let dir = std::env::temp_dir().join(format!("speedtest-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let notifier = Notifier::with_socket(Some(path), Some(Duration::from_millis(100)));
+        let receive = || {
+            let mut buf = [0; 256];
+            let len = socket.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        };
+
+        notifier.waiting();
+        assert_eq!(receive(), "READY=1\nSTATUS=Waiting for the first cycle\n");
+        notifier.alive_during(tokio::time::sleep(Duration::from_millis(80))).await;
+        assert_eq!(receive(), "WATCHDOG=1\n");
+        notifier.cycle_done("1 result");
+        assert_eq!(receive(), "STATUS=1 result\nWATCHDOG=1\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn abstract_socket_names_are_supported() {
+        use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+        let name = format!("speedtest-notify-{}", std::process::id());
+        let socket = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+        Notifier::with_socket(Some(PathBuf::from(format!("@{}", name))), None).stopping();
+
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STOPPING=1\n");
+    }
+}
@@ -0,0 +1,61 @@
+use std::{sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// How much unused rate may accumulate, so short bursts don't exceed the cap.
+const BURST: Duration = Duration::from_millis(100);
+
+/// A token bucket shared by every stream of a run, capping the combined
+/// transfer rate to simulate a slower link.
+pub struct Throttle {
+    bytes_per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Throttle {
+    pub fn new(mbps: f64) -> Self {
+        Throttle {
+            bytes_per_sec: mbps * 1_000_000.0 / 8.0,
+            bucket: Mutex::new(Bucket { tokens: 0.0, refilled: Instant::now() }),
+        }
+    }
+
+    /// Accounts for `bytes` just transferred, sleeping until the bucket has
+    /// paid them off. Tokens may go negative, which makes later callers wait.
+    pub async fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let capacity = self.bytes_per_sec * BURST.as_secs_f64();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.bytes_per_sec;
+            bucket.tokens = (bucket.tokens + refill).min(capacity) - bytes as f64;
+            bucket.refilled = now;
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec))
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn consume_paces_to_the_rate() {
+        // 80 Mbps is 10 MB/s, so 3 MB takes about 300ms.
+        let throttle = Throttle::new(80.0);
+        let start = Instant::now();
+        for _ in 0..30 {
+            throttle.consume(100_000).await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(280), "took {:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(600), "took {:?}", elapsed);
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How often a `SlowStartAbort` re-checks the rate.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The start of a transfer reported as its burst rate, before shaping such
+/// as PowerBoost typically throttles it.
+pub const BURST_WINDOW: Duration = Duration::from_secs(2);
+
+/// Throughput of one time bucket of a transfer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistogramBucket {
+    /// Offset of the bucket from the start of the transfer.
+    pub bucket_ms: u64,
+    pub mbps: f64,
+}
+
+/// Counts transferred bytes into fixed-width time buckets, shared by every
+/// stream of a phase.
+pub struct ThroughputRecorder {
+    start: Instant,
+    bucket: Duration,
+    buckets: Mutex<Vec<u64>>,
+}
+
+impl ThroughputRecorder {
+    pub fn new(bucket: Duration) -> Self {
+        ThroughputRecorder { start: Instant::now(), bucket, buckets: Mutex::new(Vec::new()) }
+    }
+
+    pub fn record(&self, bytes: usize) {
+        let index = (self.start.elapsed().as_nanos() / self.bucket.as_nanos()) as usize;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() <= index {
+            buckets.resize(index + 1, 0);
+        }
+        buckets[index] += bytes as u64;
+    }
+
+    /// Bytes recorded so far.
+    pub fn total_bytes(&self) -> u64 {
+        self.buckets.lock().unwrap().iter().sum()
+    }
+
+    /// Average Mbps over the first `window` (rounded down to whole buckets)
+    /// and over the rest of the transfer up to `elapsed`; the latter is
+    /// `None` if the transfer didn't outlast the window.
+    pub fn burst_and_sustained(&self, window: Duration, elapsed: Duration) -> (f64, Option<f64>) {
+        let buckets = self.buckets.lock().unwrap();
+        let split = (window.as_nanos() / self.bucket.as_nanos()) as usize;
+        let boundary = self.bucket * split as u32;
+        let bytes = |range: &[u64]| range.iter().sum::<u64>();
+        let (head, tail) = buckets.split_at(split.min(buckets.len()));
+
+        let burst = mbps(bytes(head), elapsed.min(boundary));
+        let sustained = (elapsed > boundary).then(|| mbps(bytes(tail), elapsed - boundary));
+        (burst, sustained)
+    }
+
+    /// Average Mbps between `skip_start` and `skip_end` before `elapsed`,
+    /// over the whole buckets inside that window, or `None` if no whole
+    /// bucket fits between the ramp and the tail.
+    pub fn steady(&self, skip_start: Duration, skip_end: Duration, elapsed: Duration) -> Option<f64> {
+        let buckets = self.buckets.lock().unwrap();
+        let bucket = self.bucket.as_nanos();
+        let first = skip_start.as_nanos().div_ceil(bucket) as usize;
+        let last = (elapsed.saturating_sub(skip_end).as_nanos() / bucket) as usize;
+        if last <= first {
+            return None;
+        }
+        let bytes: u64 = buckets.iter().take(last).skip(first).sum();
+        Some(mbps(bytes, self.bucket * (last - first) as u32))
+    }
+
+    /// Per-bucket throughput up to `elapsed` after the start; the last,
+    /// partial bucket is scaled by its actual width.
+    pub fn histogram(&self, elapsed: Duration) -> Vec<HistogramBucket> {
+        let buckets = self.buckets.lock().unwrap();
+        buckets
+            .iter()
+            .enumerate()
+            .map(|(i, &bytes)| {
+                let offset = self.bucket * i as u32;
+                let width = elapsed.saturating_sub(offset).min(self.bucket).max(Duration::from_millis(1));
+                HistogramBucket {
+                    bucket_ms: offset.as_millis() as u64,
+                    mbps: bytes as f64 * 8.0 / width.as_secs_f64() / 1_000_000.0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Gives up on a transfer that is still below `threshold_mbps` once `after`
+/// has elapsed, instead of waiting for the full size on a degraded link.
+pub struct SlowStartAbort {
+    pub threshold_mbps: f64,
+    pub after: Duration,
+}
+
+impl SlowStartAbort {
+    pub fn too_slow(&self, bytes: u64, elapsed: Duration) -> bool {
+        elapsed >= self.after && mbps(bytes, elapsed) < self.threshold_mbps
+    }
+
+    /// Resolves with the measured rate once the transfer started at `start`
+    /// (having moved `bytes()` so far) is judged too slow; never otherwise.
+    pub async fn watch(&self, start: Instant, bytes: impl Fn() -> u64) -> f64 {
+        loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+            let (bytes, elapsed) = (bytes(), start.elapsed());
+            if self.too_slow(bytes, elapsed) {
+                return mbps(bytes, elapsed);
+            }
+        }
+    }
+}
+
+fn mbps(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 * 8.0 / elapsed.as_secs_f64().max(f64::EPSILON) / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_bytes_into_time_buckets() {
+        let recorder = ThroughputRecorder::new(Duration::from_millis(100));
+        recorder.record(125_000);
+        std::thread::sleep(Duration::from_millis(110));
+        recorder.record(250_000);
+
+        let histogram = recorder.histogram(Duration::from_millis(150));
+        assert_eq!(histogram.len(), 2);
+        assert_eq!(histogram[0], HistogramBucket { bucket_ms: 0, mbps: 10.0 });
+        // The last bucket is only 50ms wide.
+        assert_eq!(histogram[1], HistogramBucket { bucket_ms: 100, mbps: 40.0 });
+        assert_eq!(recorder.total_bytes(), 375_000);
+    }
+
+    #[test]
+    fn steady_rate_skips_the_ramp_and_the_tail() {
+        let recorder = ThroughputRecorder::new(Duration::from_millis(50));
+        recorder.buckets.lock().unwrap().extend([10_000, 62_500, 62_500, 62_500, 1_000]);
+
+        // Buckets 1-3 carry 0.5 Mbit each over 50ms.
+        let steady = recorder.steady(Duration::from_millis(40), Duration::from_millis(60), Duration::from_millis(250));
+        assert!((steady.unwrap() - 10.0).abs() < 1e-9, "got {:?}", steady);
+        assert_eq!(
+            recorder.steady(Duration::from_millis(200), Duration::from_millis(60), Duration::from_millis(250)),
+            None
+        );
+    }
+
+    #[test]
+    fn splits_the_burst_from_the_sustained_rate() {
+        let recorder = ThroughputRecorder::new(Duration::from_millis(50));
+        recorder.record(250_000);
+        std::thread::sleep(Duration::from_millis(110));
+        recorder.record(25_000);
+
+        // 2 Mbit in the first 100ms, then 0.2 Mbit over the next 100ms.
+        let (burst, sustained) = recorder.burst_and_sustained(Duration::from_millis(100), Duration::from_millis(200));
+        assert!((burst - 20.0).abs() < 1e-9, "got {}", burst);
+        assert!((sustained.unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(recorder.burst_and_sustained(Duration::from_millis(100), Duration::from_millis(80)).1, None);
+    }
+
+    #[test]
+    fn slow_start_waits_for_the_window_before_judging() {
+        let abort = SlowStartAbort { threshold_mbps: 1.0, after: Duration::from_secs(5) };
+        // 100 KB in 4s is 0.2 Mbps, but the window hasn't passed yet.
+        assert!(!abort.too_slow(100_000, Duration::from_secs(4)));
+        assert!(abort.too_slow(100_000, Duration::from_secs(5)));
+        assert!(!abort.too_slow(10_000_000, Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,213 @@
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// GitHub API endpoint describing the newest published release.
+pub const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/psucodervn/speedtest-cli/releases/latest";
+/// Time allowed for the release lookup, so `version --check` never hangs.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    /// Whether this release is newer than `current` (e.g. `0.1.0`).
+    pub fn is_newer_than(&self, current: &str) -> bool {
+        version_parts(&self.tag_name) > version_parts(current)
+    }
+
+    /// Download URL of the archive built for this platform, if one was published.
+    fn asset_url(&self) -> Option<&str> {
+        self.url_of(asset_name()?)
+    }
+
+    /// Download URL of the `.sha256` file published next to this platform's archive.
+    fn checksum_url(&self) -> Option<&str> {
+        self.url_of(&format!("{}.sha256", asset_name()?))
+    }
+
+    fn url_of(&self, name: &str) -> Option<&str> {
+        self.assets.iter().find(|asset| asset.name == name).map(|asset| asset.browser_download_url.as_str())
+    }
+}
+
+/// `v1.10.2` as `[1, 10, 2]`, so versions compare numerically.
+fn version_parts(version: &str) -> Vec<u64> {
+    version.trim_start_matches('v').split(['.', '-']).map_while(|part| part.parse().ok()).collect()
+}
+
+/// The release archive for this platform, named as the release workflow names it.
+fn asset_name() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("speedtest-linux-x86_64.tar.gz"),
+        ("macos", "x86_64") => Some("speedtest-macos-x86_64.tar.gz"),
+        ("windows", "x86_64") => Some("speedtest-windows-x86_64.exe.zip"),
+        _ => None,
+    }
+}
+
+/// The newest release according to `url` (`LATEST_RELEASE_URL` outside tests).
+pub async fn latest_release(client: &Client, url: &str) -> Result<Release, String> {
+    let response = client
+        .get(url)
+        .timeout(CHECK_TIMEOUT)
+        // The GitHub API rejects requests without a user agent.
+        .header(reqwest::header::USER_AGENT, concat!("speedtest-cli/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("could not look up the latest release: {}", e))?;
+    let body = response.bytes().await.map_err(|e| format!("could not look up the latest release: {}", e))?;
+    serde_json::from_slice(&body).map_err(|e| format!("unexpected release information: {}", e))
+}
+
+/// Downloads `release`'s archive for this platform, checks it against the
+/// published SHA-256 and replaces the running binary with the one inside.
+/// Returns the path that was replaced.
+pub async fn install(client: &Client, release: &Release) -> Result<PathBuf, String> {
+    let url =
+        release.asset_url().ok_or_else(|| format!("release {} has no build for this platform", release.tag_name))?;
+    let checksum_url = release
+        .checksum_url()
+        .ok_or_else(|| format!("release {} publishes no checksum for this platform's build", release.tag_name))?;
+    let archive = download(client, url).await?;
+    let checksum = download(client, checksum_url).await?;
+    verify(&archive, &String::from_utf8_lossy(&checksum))?;
+
+    let exe = std::env::current_exe().map_err(|e| format!("cannot locate the running binary: {}", e))?;
+    let dir = exe.parent().ok_or("cannot locate the running binary")?;
+    let staging = dir.join(format!(".speedtest-update-{}", std::process::id()));
+    std::fs::create_dir_all(&staging).map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+    let installed = unpack_and_replace(&archive, &staging, &exe);
+    let _ = std::fs::remove_dir_all(&staging);
+    installed.map(|()| exe)
+}
+
+async fn download(client: &Client, url: &str) -> Result<bytes::Bytes, String> {
+    client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("download of {} failed: {}", url, e))?
+        .bytes()
+        .await
+        .map_err(|e| format!("download of {} failed: {}", url, e))
+}
+
+/// Checks `archive` against a `sha256sum`-style checksum file: the hex
+/// digest, optionally followed by the file name.
+fn verify(archive: &[u8], checksum_file: &str) -> Result<(), String> {
+    let expected = checksum_file.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+    let actual = format!("{:x}", Sha256::digest(archive));
+    match actual == expected {
+        true => Ok(()),
+        false => Err(format!("the downloaded archive's SHA-256 is {}, but the release publishes {}", actual, expected)),
+    }
+}
+
+fn unpack_and_replace(archive: &[u8], staging: &Path, exe: &Path) -> Result<(), String> {
+    let archive_path = staging.join("release-archive");
+    std::fs::write(&archive_path, archive).map_err(|e| e.to_string())?;
+    // The system tar reads both the .tar.gz and, on Windows, the .zip archives.
+    let status = std::process::Command::new("tar")
+        .arg("-xf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(staging)
+        .status()
+        .map_err(|e| format!("cannot run tar to unpack the release: {}", e))?;
+    if !status.success() {
+        return Err("unpacking the release archive failed".to_string());
+    }
+    let binary = staging.join(if cfg!(windows) { "speedtest-cli.exe" } else { "speedtest-cli" });
+    if !binary.is_file() {
+        return Err("the release archive has no speedtest-cli binary".to_string());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+    }
+    // Windows won't overwrite a running executable but lets it be renamed.
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("old.exe");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old).map_err(|e| format!("cannot replace {}: {}", exe.display(), e))?;
+    }
+    std::fs::rename(&binary, exe).map_err(|e| format!("cannot replace {}: {}", exe.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn versions_compare_numerically() {
+        let release = |tag: &str| Release { tag_name: tag.to_string(), assets: Vec::new() };
+        assert!(release("v0.10.0").is_newer_than("0.9.3"));
+        assert!(release("v1.0.0").is_newer_than("0.1.0"));
+        assert!(!release("v0.1.0").is_newer_than("0.1.0"));
+        assert!(!release("0.0.9").is_newer_than("0.1.0"));
+    }
+
+    #[tokio::test]
+    async fn the_latest_release_is_read_from_the_api() {
+        let mock = MockServer::start().await;
+        let body = serde_json::json!({
+            "tag_name": "v0.2.0",
+            "assets": [{"name": "speedtest-linux-x86_64.tar.gz", "browser_download_url": "https://example.com/linux"}],
+        });
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200).set_body_json(body)).mount(&mock).await;
+
+        let release = latest_release(&Client::new(), &mock.uri()).await.unwrap();
+        assert_eq!(release.tag_name, "v0.2.0");
+        if asset_name() == Some("speedtest-linux-x86_64.tar.gz") {
+            assert_eq!(release.asset_url(), Some("https://example.com/linux"));
+        }
+
+        let down = latest_release(&Client::new(), "http://127.0.0.1:1/").await.unwrap_err();
+        assert!(down.starts_with("could not look up the latest release"), "{}", down);
+    }
+
+    #[test]
+    fn archives_are_checked_against_the_published_checksum() {
+        let digest = "b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c";
+        assert!(verify(b"foo\n", &format!("{}  speedtest-linux-x86_64.tar.gz\n", digest)).is_ok());
+        assert!(verify(b"foo\n", &digest.to_uppercase()).is_ok());
+        let err = verify(b"tampered", digest).unwrap_err();
+        assert!(err.ends_with(&format!("but the release publishes {}", digest)), "{}", err);
+        assert!(verify(b"foo\n", "").is_err());
+    }
+
+    #[tokio::test]
+    async fn a_mismatched_archive_is_not_installed() {
+        let Some(name) = asset_name() else { return };
+        let mock = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200).set_body_string("0000")).mount(&mock).await;
+        let asset = |name: String| Asset { browser_download_url: format!("{}/{}", mock.uri(), name), name };
+        let mut release = Release { tag_name: "v9.9.9".to_string(), assets: vec![asset(name.to_string())] };
+
+        let err = install(&Client::new(), &release).await.unwrap_err();
+        assert_eq!(err, "release v9.9.9 publishes no checksum for this platform's build");
+        release.assets.push(asset(format!("{}.sha256", name)));
+        let err = install(&Client::new(), &release).await.unwrap_err();
+        assert!(err.starts_with("the downloaded archive's SHA-256 is"), "{}", err);
+    }
+}